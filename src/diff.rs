@@ -0,0 +1,533 @@
+//! For finding what changed between two versions of a [`CommandInfo`], e.g.
+//! to generate changelogs
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{Arg, ArgType, CommandInfo, Flag, FlagArg};
+
+/// Everything that differs between two commands
+///
+/// Each entry has the path to the command it's in, starting with the name of
+/// the top-level command, e.g. `["git", "commit"]`.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+pub struct CommandDiff {
+  /// Flags and subcommands only in the new command
+  pub added: Vec<DiffEntry>,
+  /// Flags and subcommands only in the old command
+  pub removed: Vec<DiffEntry>,
+  /// Things that are in both commands but are different
+  pub changed: Vec<Change>,
+}
+
+/// A flag or subcommand that was added or removed
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffEntry {
+  Flag {
+    cmd: Vec<String>,
+    flag: Flag,
+  },
+  /// For subcommands, `cmd` is the path to the subcommand's parent
+  Subcommand {
+    cmd: Vec<String>,
+    subcommand: CommandInfo,
+  },
+}
+
+/// Something in both commands that was changed
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+  Desc {
+    cmd: Vec<String>,
+    old: Option<String>,
+    new: Option<String>,
+  },
+  Args {
+    cmd: Vec<String>,
+    old: Vec<Arg>,
+    new: Vec<Arg>,
+  },
+  Exclusive {
+    cmd: Vec<String>,
+    old: Vec<Vec<String>>,
+    new: Vec<Vec<String>>,
+  },
+  Aliases {
+    cmd: Vec<String>,
+    old: Vec<String>,
+    new: Vec<String>,
+  },
+  Hidden {
+    cmd: Vec<String>,
+    old: bool,
+    new: bool,
+  },
+  Deprecated {
+    cmd: Vec<String>,
+    old: bool,
+    new: bool,
+  },
+  /// Flags are considered the same flag if they share any forms
+  Flag {
+    cmd: Vec<String>,
+    old: Box<Flag>,
+    new: Box<Flag>,
+  },
+}
+
+impl CommandDiff {
+  /// Whether the two commands were the same
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+  }
+}
+
+impl fmt::Display for CommandDiff {
+  /// A human-readable report, with one line per added (`+`), removed (`-`), or
+  /// changed (`~`) thing
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "No differences");
+    }
+    for entry in &self.added {
+      writeln!(f, "+ {entry}")?;
+    }
+    for entry in &self.removed {
+      writeln!(f, "- {entry}")?;
+    }
+    for change in &self.changed {
+      write!(f, "{change}")?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for DiffEntry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DiffEntry::Flag { cmd, flag } => {
+        write!(f, "{} {}", cmd.join(" "), flag.forms.join(", "))
+      }
+      DiffEntry::Subcommand { cmd, subcommand } => {
+        write!(f, "{} {} (subcommand)", cmd.join(" "), subcommand.name)
+      }
+    }
+  }
+}
+
+impl fmt::Display for Change {
+  /// Changes to flags may take up multiple lines, one for each thing about
+  /// the flag that changed
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Change::Desc { cmd, old, new } => writeln!(
+        f,
+        "~ {}: description {} -> {}",
+        cmd.join(" "),
+        fmt_desc(old),
+        fmt_desc(new)
+      ),
+      Change::Args { cmd, old, new } => writeln!(
+        f,
+        "~ {}: args {} -> {}",
+        cmd.join(" "),
+        fmt_args(old),
+        fmt_args(new)
+      ),
+      Change::Exclusive { cmd, old, new } => {
+        writeln!(f, "~ {}: exclusive flags {old:?} -> {new:?}", cmd.join(" "))
+      }
+      Change::Aliases { cmd, old, new } => {
+        writeln!(f, "~ {}: aliases {old:?} -> {new:?}", cmd.join(" "))
+      }
+      Change::Hidden { cmd, old, new } => {
+        writeln!(f, "~ {}: hidden {old} -> {new}", cmd.join(" "))
+      }
+      Change::Deprecated { cmd, old, new } => {
+        writeln!(f, "~ {}: deprecated {old} -> {new}", cmd.join(" "))
+      }
+      Change::Flag { cmd, old, new } => {
+        let name = format!("{} {}", cmd.join(" "), old.forms.join(", "));
+        if !same_forms(old, new) {
+          writeln!(f, "~ {name}: forms -> {}", new.forms.join(", "))?;
+        }
+        if old.desc != new.desc {
+          writeln!(
+            f,
+            "~ {name}: description {} -> {}",
+            fmt_desc(&old.desc),
+            fmt_desc(&new.desc)
+          )?;
+        }
+        if old.typ != new.typ {
+          writeln!(
+            f,
+            "~ {name}: type {} -> {}",
+            fmt_typ(&old.typ),
+            fmt_typ(&new.typ)
+          )?;
+        }
+        if old.arg != new.arg {
+          writeln!(
+            f,
+            "~ {name}: argument {} -> {}",
+            fmt_arg(&old.arg),
+            fmt_arg(&new.arg)
+          )?;
+        }
+        if old.requires != new.requires {
+          writeln!(
+            f,
+            "~ {name}: requires {:?} -> {:?}",
+            old.requires, new.requires
+          )?;
+        }
+        if old.repeatable != new.repeatable {
+          writeln!(
+            f,
+            "~ {name}: repeatable {} -> {}",
+            old.repeatable, new.repeatable
+          )?;
+        }
+        if old.persistent != new.persistent {
+          writeln!(
+            f,
+            "~ {name}: persistent {} -> {}",
+            old.persistent, new.persistent
+          )?;
+        }
+        if old.conflicts != new.conflicts {
+          writeln!(
+            f,
+            "~ {name}: conflicts {:?} -> {:?}",
+            old.conflicts, new.conflicts
+          )?;
+        }
+        if old.aliases != new.aliases {
+          writeln!(
+            f,
+            "~ {name}: aliases {:?} -> {:?}",
+            old.aliases, new.aliases
+          )?;
+        }
+        if old.hidden != new.hidden {
+          writeln!(f, "~ {name}: hidden {} -> {}", old.hidden, new.hidden)?;
+        }
+        if old.deprecated != new.deprecated {
+          writeln!(
+            f,
+            "~ {name}: deprecated {} -> {}",
+            old.deprecated, new.deprecated
+          )?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+fn fmt_desc(desc: &Option<String>) -> String {
+  match desc {
+    Some(desc) => format!("{desc:?}"),
+    None => "(none)".to_owned(),
+  }
+}
+
+fn fmt_typ(typ: &Option<ArgType>) -> String {
+  match typ {
+    Some(typ) => format!("{typ:?}"),
+    None => "(none)".to_owned(),
+  }
+}
+
+fn fmt_arg(arg: &Option<FlagArg>) -> String {
+  match arg {
+    Some(arg) => format!(
+      "{} ({}, {})",
+      arg.name.as_deref().unwrap_or("unnamed"),
+      arg.count,
+      arg.style
+    ),
+    None => "(none)".to_owned(),
+  }
+}
+
+/// Show arguments like a usage string, e.g. `FILE: Path [REST...]`
+fn fmt_args(args: &[Arg]) -> String {
+  if args.is_empty() {
+    return "(none)".to_owned();
+  }
+  args
+    .iter()
+    .map(|arg| {
+      let mut s = arg.name.as_deref().unwrap_or("arg").to_owned();
+      if arg.variadic {
+        s.push_str("...");
+      }
+      if let Some(typ) = &arg.typ {
+        s.push_str(&format!(": {typ:?}"));
+      }
+      if arg.optional {
+        format!("[{s}]")
+      } else {
+        s
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+impl CommandInfo {
+  /// Find everything that changed going from `self` to `other`. The
+  /// top-level commands are compared even if their names are different.
+  #[must_use]
+  pub fn diff(&self, other: &CommandInfo) -> CommandDiff {
+    let mut diff = CommandDiff::default();
+    diff_cmds(&[], self, other, &mut diff);
+    diff
+  }
+}
+
+fn diff_cmds(
+  parent: &[String],
+  old: &CommandInfo,
+  new: &CommandInfo,
+  diff: &mut CommandDiff,
+) {
+  let mut path = parent.to_vec();
+  path.push(new.name.clone());
+
+  if old.desc != new.desc {
+    diff.changed.push(Change::Desc {
+      cmd: path.clone(),
+      old: old.desc.clone(),
+      new: new.desc.clone(),
+    });
+  }
+  if old.args != new.args {
+    diff.changed.push(Change::Args {
+      cmd: path.clone(),
+      old: old.args.clone(),
+      new: new.args.clone(),
+    });
+  }
+  if old.exclusive != new.exclusive {
+    diff.changed.push(Change::Exclusive {
+      cmd: path.clone(),
+      old: old.exclusive.clone(),
+      new: new.exclusive.clone(),
+    });
+  }
+  if old.aliases != new.aliases {
+    diff.changed.push(Change::Aliases {
+      cmd: path.clone(),
+      old: old.aliases.clone(),
+      new: new.aliases.clone(),
+    });
+  }
+  if old.hidden != new.hidden {
+    diff.changed.push(Change::Hidden {
+      cmd: path.clone(),
+      old: old.hidden,
+      new: new.hidden,
+    });
+  }
+  if old.deprecated != new.deprecated {
+    diff.changed.push(Change::Deprecated {
+      cmd: path.clone(),
+      old: old.deprecated,
+      new: new.deprecated,
+    });
+  }
+
+  for old_flag in &old.flags {
+    match find_flag(&new.flags, old_flag) {
+      Some(new_flag) => {
+        if !same_flag(old_flag, new_flag) {
+          diff.changed.push(Change::Flag {
+            cmd: path.clone(),
+            old: Box::new(old_flag.clone()),
+            new: Box::new(new_flag.clone()),
+          });
+        }
+        // Forms that no longer exist anywhere are as breaking as a removed
+        // flag, so report them as removals too
+        let lost: Vec<String> = old_flag
+          .forms
+          .iter()
+          .filter(|form| {
+            !new.flags.iter().any(|flag| flag.forms.contains(form))
+          })
+          .cloned()
+          .collect();
+        if !lost.is_empty() {
+          diff.removed.push(DiffEntry::Flag {
+            cmd: path.clone(),
+            flag: Flag {
+              forms: lost,
+              ..old_flag.clone()
+            },
+          });
+        }
+      }
+      None => diff.removed.push(DiffEntry::Flag {
+        cmd: path.clone(),
+        flag: old_flag.clone(),
+      }),
+    }
+  }
+  for new_flag in &new.flags {
+    if find_flag(&old.flags, new_flag).is_none() {
+      diff.added.push(DiffEntry::Flag {
+        cmd: path.clone(),
+        flag: new_flag.clone(),
+      });
+    }
+  }
+
+  for old_sub in &old.subcommands {
+    match new.subcommands.iter().find(|sub| sub.name == old_sub.name) {
+      Some(new_sub) => diff_cmds(&path, old_sub, new_sub, diff),
+      None => diff.removed.push(DiffEntry::Subcommand {
+        cmd: path.clone(),
+        subcommand: old_sub.clone(),
+      }),
+    }
+  }
+  for new_sub in &new.subcommands {
+    if !old.subcommands.iter().any(|sub| sub.name == new_sub.name) {
+      diff.added.push(DiffEntry::Subcommand {
+        cmd: path.clone(),
+        subcommand: new_sub.clone(),
+      });
+    }
+  }
+}
+
+/// Find a flag sharing any forms with the given flag
+fn find_flag<'a>(flags: &'a [Flag], flag: &Flag) -> Option<&'a Flag> {
+  flags
+    .iter()
+    .find(|other| other.forms.iter().any(|form| flag.forms.contains(form)))
+}
+
+/// Whether two flags are the same, ignoring the order of their forms
+fn same_flag(a: &Flag, b: &Flag) -> bool {
+  a.desc == b.desc
+    && a.typ == b.typ
+    && a.arg == b.arg
+    && a.requires == b.requires
+    && a.conflicts == b.conflicts
+    && a.repeatable == b.repeatable
+    && a.persistent == b.persistent
+    && a.aliases == b.aliases
+    && a.hidden == b.hidden
+    && a.deprecated == b.deprecated
+    && same_forms(a, b)
+}
+
+fn same_forms(a: &Flag, b: &Flag) -> bool {
+  a.forms.len() == b.forms.len()
+    && a.forms.iter().all(|form| b.forms.contains(form))
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::{Change, CommandDiff, DiffEntry};
+  use crate::{
+    test_util::{cmd, flag},
+    ArgType,
+  };
+
+  fn path(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+
+  #[test]
+  fn test_same() {
+    let old = cmd("foo", vec![flag(&["-a", "--all"], None)], vec![]);
+    let new = cmd("foo", vec![flag(&["--all", "-a"], None)], vec![]);
+    assert!(old.diff(&new).is_empty());
+  }
+
+  #[test]
+  fn test_diff() {
+    let old = cmd(
+      "foo",
+      vec![flag(&["-a"], Some("All")), flag(&["-q"], None)],
+      vec![
+        cmd("bar", vec![flag(&["-x"], None)], vec![]),
+        cmd("gone", vec![], vec![]),
+      ],
+    );
+    let mut new = cmd(
+      "foo",
+      vec![flag(&["-a", "--all"], Some("All")), flag(&["-v"], None)],
+      vec![cmd("bar", vec![], vec![]), cmd("baz", vec![], vec![])],
+    );
+    new.subcommands[0].args = vec![ArgType::Path.into()];
+
+    assert_eq!(
+      CommandDiff {
+        added: vec![
+          DiffEntry::Flag {
+            cmd: path(&["foo"]),
+            flag: flag(&["-v"], None)
+          },
+          DiffEntry::Subcommand {
+            cmd: path(&["foo"]),
+            subcommand: cmd("baz", vec![], vec![])
+          },
+        ],
+        removed: vec![
+          DiffEntry::Flag {
+            cmd: path(&["foo"]),
+            flag: flag(&["-q"], None)
+          },
+          DiffEntry::Flag {
+            cmd: path(&["foo", "bar"]),
+            flag: flag(&["-x"], None)
+          },
+          DiffEntry::Subcommand {
+            cmd: path(&["foo"]),
+            subcommand: cmd("gone", vec![], vec![])
+          },
+        ],
+        changed: vec![
+          Change::Flag {
+            cmd: path(&["foo"]),
+            old: Box::new(flag(&["-a"], Some("All"))),
+            new: Box::new(flag(&["-a", "--all"], Some("All"))),
+          },
+          Change::Args {
+            cmd: path(&["foo", "bar"]),
+            old: vec![],
+            new: vec![ArgType::Path.into()],
+          },
+        ],
+      },
+      old.diff(&new)
+    );
+  }
+
+  #[test]
+  fn test_removed_form() {
+    let old = cmd("foo", vec![flag(&["--foo", "-f"], None)], vec![]);
+    let new = cmd("foo", vec![flag(&["--foo"], None)], vec![]);
+    let diff = old.diff(&new);
+    assert_eq!(
+      vec![DiffEntry::Flag {
+        cmd: path(&["foo"]),
+        flag: flag(&["-f"], None)
+      }],
+      diff.removed
+    );
+    assert_eq!(1, diff.changed.len());
+  }
+}
@@ -1,16 +1,96 @@
-use crate::gen::{util::Output, CommandInfo};
+use crate::{
+  gen::{
+    util::{quote_bash, Output},
+    CommandInfo,
+  },
+  ArgStyle, ArgType, ValueCount,
+};
 
 /// Generate a completion file for Bash
 pub fn generate(cmd: &CommandInfo) -> (String, String) {
   let comp_name = format!("_comp_cmd_{}", cmd.name);
 
+  let unused_fn = format!("{comp_name}_unused");
+  let hidden_fn = format!("{comp_name}_hidden");
+  let positional_fn = format!("{comp_name}_positional");
+
   let mut out = Output::new(String::from("\t"));
   out.writeln("#!/usr/bin/env bash\n");
+
+  if has_single_use(cmd) {
+    // Takes the index of the (sub)command's first word, then the forms of each
+    // flag as a single argument, and prints the flags that haven't been used
+    // yet
+    out.writeln(format!("function {unused_fn} {{"));
+    out.indent();
+    out.writeln("local start=$1 flag form i");
+    out.writeln("shift");
+    out.writeln(r#"for flag in "$@"; do"#);
+    out.indent();
+    out.writeln("for form in $flag; do");
+    out.indent();
+    out.writeln("for ((i = start; i < COMP_CWORD; i++)); do");
+    out.indent();
+    out.writeln(r#"[[ ${COMP_WORDS[i]} == "$form" ]] && continue 3"#);
+    out.dedent();
+    out.writeln("done");
+    out.dedent();
+    out.writeln("done");
+    out.writeln(r#"echo "$flag""#);
+    out.dedent();
+    out.writeln("done");
+    out.dedent();
+    out.writeln("}");
+    out.writeln("");
+  }
+
+  if has_hidden(cmd) {
+    // Takes the word being completed, then words that should only be
+    // completed once the user has started typing them
+    out.writeln(format!("function {hidden_fn} {{"));
+    out.indent();
+    out.writeln(r#"[[ -n ${1//-} ]] && echo "${@:2}""#);
+    out.dedent();
+    out.writeln("}");
+    out.writeln("");
+  }
+
+  if has_args(cmd) {
+    // Takes the index of the command's first word and the forms of flags that
+    // take a value as the next word, and prints how many arguments come
+    // before the word being completed
+    out.writeln(format!("function {positional_fn} {{"));
+    out.indent();
+    out.writeln(r#"local start=$1 flags=" $2 " n=0 i"#);
+    out.writeln("for ((i = start; i < COMP_CWORD; i++)); do");
+    out.indent();
+    out.writeln("if [[ ${COMP_WORDS[i]} == -* ]]; then");
+    out.indent();
+    out.writeln(r#"[[ $flags == *" ${COMP_WORDS[i]} "* ]] && ((i++))"#);
+    out.dedent();
+    out.writeln("else");
+    out.indent();
+    out.writeln("((n++))");
+    out.dedent();
+    out.writeln("fi");
+    out.dedent();
+    out.writeln("done");
+    out.writeln(r#"echo "$n""#);
+    out.dedent();
+    out.writeln("}");
+    out.writeln("");
+  }
+
   out.writeln(format!("function {comp_name} {{"));
   out.indent();
   out.writeln("COMPREPLY=()");
 
-  generate_cmd(cmd, 1, &mut out);
+  let fns = HelperFns {
+    unused: &unused_fn,
+    hidden: &hidden_fn,
+    positional: &positional_fn,
+  };
+  generate_cmd(cmd, 1, &fns, &mut out);
 
   out.writeln("return 0");
   out.dedent();
@@ -23,22 +103,104 @@ pub fn generate(cmd: &CommandInfo) -> (String, String) {
   (format!("_{}.bash", cmd.name), out.text())
 }
 
-fn generate_cmd(cmd: &CommandInfo, pos: usize, out: &mut Output) {
+/// Whether any flags in this command or its subcommands can only be used once
+fn has_single_use(cmd: &CommandInfo) -> bool {
+  cmd.flags.iter().any(|flag| !flag.repeatable)
+    || cmd.subcommands.iter().any(has_single_use)
+}
+
+/// Whether this command or its subcommands have any hidden flags or
+/// subcommands, or any aliases
+fn has_hidden(cmd: &CommandInfo) -> bool {
+  cmd
+    .flags
+    .iter()
+    .any(|flag| flag.hidden || !flag.aliases.is_empty())
+    || cmd
+      .subcommands
+      .iter()
+      .any(|sub| sub.hidden || !sub.aliases.is_empty() || has_hidden(sub))
+}
+
+/// Whether this command or any of its subcommands has positional arguments
+/// to complete
+fn has_args(cmd: &CommandInfo) -> bool {
+  if cmd.subcommands.is_empty() {
+    !cmd.args.is_empty()
+  } else {
+    cmd.subcommands.iter().any(has_args)
+  }
+}
+
+/// The names of the helper functions defined at the top of the file
+struct HelperFns<'a> {
+  unused: &'a str,
+  hidden: &'a str,
+  positional: &'a str,
+}
+
+fn generate_cmd(
+  cmd: &CommandInfo,
+  pos: usize,
+  fns: &HelperFns,
+  out: &mut Output,
+) {
+  let HelperFns {
+    unused: unused_fn,
+    hidden: hidden_fn,
+    positional: positional_fn,
+  } = fns;
   out.writeln("case $COMP_CWORD in");
   out.indent();
 
+  // Single-use flags are passed through `unused_fn` to hide them once used
+  let single_use = cmd
+    .flags
+    .iter()
+    .filter(|f| !f.repeatable && !f.hidden)
+    .map(|f| quote_bash(f.forms.join(" ")))
+    .collect::<Vec<_>>()
+    .join(" ");
   let flags = cmd
     .flags
     .iter()
+    .filter(|f| f.repeatable && !f.hidden)
     .map(|f| f.forms.join(" "))
     .collect::<Vec<_>>()
     .join(" ");
   let subcmds = cmd
     .subcommands
     .iter()
+    .filter(|c| !c.hidden)
     .map(|c| c.name.to_string())
     .collect::<Vec<_>>()
     .join(" ");
+  // Hidden flags and subcommands and aliases are passed through `hidden_fn`
+  // so they're only completed once they're being typed
+  let hidden_single_use = cmd
+    .flags
+    .iter()
+    .filter(|f| !f.repeatable && f.hidden)
+    .map(|f| quote_bash(f.forms.join(" ")))
+    .collect::<Vec<_>>();
+  let hidden = cmd
+    .flags
+    .iter()
+    .flat_map(|f| {
+      let forms = if f.hidden && f.repeatable {
+        &f.forms[..]
+      } else {
+        &[]
+      };
+      forms.iter().chain(&f.aliases)
+    })
+    .chain(cmd.subcommands.iter().flat_map(|c| {
+      let name = if c.hidden { Some(&c.name) } else { None };
+      name.into_iter().chain(&c.aliases)
+    }))
+    .map(String::as_str)
+    .collect::<Vec<_>>()
+    .join(" ");
   let completions = if flags.is_empty() {
     subcmds
   } else if subcmds.is_empty() {
@@ -46,31 +208,244 @@ fn generate_cmd(cmd: &CommandInfo, pos: usize, out: &mut Output) {
   } else {
     format!("{flags} {subcmds}")
   };
+  let mut words = if single_use.is_empty() {
+    format!("'{completions}'")
+  } else if completions.is_empty() {
+    format!(r#""$({unused_fn} {pos} {single_use})""#)
+  } else {
+    format!(r#""$({unused_fn} {pos} {single_use})"' {completions}'"#)
+  };
+  if !hidden_single_use.is_empty() || !hidden.is_empty() {
+    let mut hidden_args = Vec::new();
+    if !hidden_single_use.is_empty() {
+      hidden_args.push(format!(
+        r#""$({unused_fn} {pos} {})""#,
+        hidden_single_use.join(" ")
+      ));
+    }
+    if !hidden.is_empty() {
+      hidden_args.push(quote_bash(hidden));
+    }
+    words.push_str(&format!(
+      r#"" $({hidden_fn} "$2" {})""#,
+      hidden_args.join(" ")
+    ));
+  }
+  // Positional arguments are only completed for commands without
+  // subcommands, and are handled along with everything after the first word
+  let has_args = cmd.subcommands.is_empty() && !cmd.args.is_empty();
+
   // This case is for when the subcommand we're processing is the one to
   // complete
-  out.writeln(format!(
-    "{pos}) COMPREPLY=($(compgen -W '{completions}' -- $2)) ;;"
-  ));
+  if !has_args {
+    out.writeln(format!("{pos}) COMPREPLY=($(compgen -W {words} -- $2)) ;;"));
+  }
+
+  // Flags whose values are given as the next word, so that the value can be
+  // completed when the previous word is one of these flags
+  let value_flags = cmd
+    .flags
+    .iter()
+    .filter(|flag| {
+      !matches!(flag.value_count(), ValueCount::Zero | ValueCount::Optional)
+        && flag.arg_style() != ArgStyle::Attached
+    })
+    .collect::<Vec<_>>();
+
+  // After the first word, keep completing flags (without the ones already
+  // used) if there are no subcommands to go to
+  if cmd.flags.is_empty() && cmd.subcommands.is_empty() && !has_args {
+    out.dedent();
+    out.writeln("esac");
+    return;
+  }
 
-  // This case is in case we need to go further to a deeper subcommand
-  if !cmd.subcommands.is_empty() {
+  out.writeln("*)");
+  out.indent();
+  if !value_flags.is_empty() {
+    out.writeln("case $3 in");
+    out.indent();
+    for flag in &value_flags {
+      let values = complete_values(flag.typ.as_ref());
+      let forms = flag
+        .forms
+        .iter()
+        .chain(&flag.aliases)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("|");
+      out.writeln(format!("{forms}) {values} ;;"));
+    }
+    out.writeln("*)");
+    out.indent();
+  }
+
+  if has_args {
+    // Words that don't start with a dash are arguments, and which argument
+    // is found by counting the arguments before it
+    let value_forms = value_flags
+      .iter()
+      .flat_map(|flag| flag.forms.iter().chain(&flag.aliases))
+      .map(String::as_str)
+      .collect::<Vec<_>>()
+      .join(" ");
+    out.writeln("case $2 in");
+    out.indent();
+    out.writeln(format!("-*) COMPREPLY=($(compgen -W {words} -- $2)) ;;"));
     out.writeln("*)");
     out.indent();
+    out.writeln(format!(
+      "case $({positional_fn} {pos} {}) in",
+      quote_bash(value_forms)
+    ));
+    out.indent();
+    for (i, arg) in cmd.args.iter().enumerate() {
+      let values = complete_values(arg.typ.as_ref());
+      if arg.variadic && i + 1 == cmd.args.len() {
+        out.writeln(format!("*) {values} ;;"));
+      } else {
+        out.writeln(format!("{i}) {values} ;;"));
+      }
+    }
+    out.dedent();
+    out.writeln("esac");
+    out.writeln(";;");
+    out.dedent();
+    out.dedent();
+    out.writeln("esac");
+    out.writeln(";;");
+  } else if cmd.subcommands.is_empty() {
+    out.writeln(format!("COMPREPLY=($(compgen -W {words} -- $2)) ;;"));
+  } else {
+    // This case is in case we need to go further to a deeper subcommand
     out.writeln(format!("case ${{COMP_WORDS[{pos}]}} in"));
     out.indent();
     for sub_cmd in &cmd.subcommands {
-      out.writeln(format!("{})", sub_cmd.name));
+      let names = std::iter::once(&sub_cmd.name)
+        .chain(&sub_cmd.aliases)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("|");
+      out.writeln(format!("{names})"));
       out.indent();
-      generate_cmd(sub_cmd, pos + 1, out);
+      generate_cmd(sub_cmd, pos + 1, fns, out);
       out.writeln(";;");
       out.dedent();
     }
     out.dedent();
     out.writeln("esac");
     out.writeln(";;");
+  }
+
+  if !value_flags.is_empty() {
+    out.dedent();
     out.dedent();
+    out.writeln("esac");
+    out.writeln(";;");
   }
+  out.dedent();
 
   out.dedent();
   out.writeln("esac");
 }
+
+/// Get the code that sets `COMPREPLY` for a flag's value or an argument,
+/// completing paths if it has no type
+fn complete_values(typ: Option<&ArgType>) -> String {
+  let Some(ArgType::List { typ, sep }) = typ else {
+    let words = typ
+      .map_or_else(|| vec!["compgen -f".to_owned()], complete_type)
+      .iter()
+      .map(|compgen| format!("$({compgen} -- $2)"))
+      .collect::<Vec<_>>()
+      .join(" ");
+    return format!("COMPREPLY=({words})");
+  };
+
+  // Only complete what's after the last separator, and put everything before
+  // it back in front of each completion
+  let sep = sep.chars().fold(String::new(), |mut s, c| {
+    if !c.is_ascii_alphanumeric() {
+      s.push('\\');
+    }
+    s.push(c);
+    s
+  });
+  let last = format!(r#""${{2##*{sep}}}""#);
+  let words = complete_type(typ)
+    .iter()
+    .map(|compgen| format!(r#"$({compgen} -P "${{2%{last}}}" -- {last})"#))
+    .collect::<Vec<_>>();
+  if words.is_empty() {
+    "COMPREPLY=()".to_owned()
+  } else {
+    // Don't add a space after a value, so that another one can be added
+    format!("COMPREPLY=({}); compopt -o nospace", words.join(" "))
+  }
+}
+
+/// Get the `compgen` commands that complete a type (without the word to
+/// complete)
+fn complete_type(typ: &ArgType) -> Vec<String> {
+  match typ {
+    ArgType::Path | ArgType::Unknown => vec!["compgen -f".to_owned()],
+    ArgType::Dir => vec!["compgen -d".to_owned()],
+    ArgType::Glob { globs, dirs } => {
+      // -X removes the files that match, so negate each pattern with !
+      let mut compgens = globs
+        .iter()
+        .map(|glob| format!("compgen -f -X {}", quote_bash(format!("!{glob}"))))
+        .collect::<Vec<_>>();
+      if *dirs {
+        compgens.push("compgen -d".to_owned());
+      }
+      compgens
+    }
+    ArgType::CommandName => vec!["compgen -c".to_owned()],
+    ArgType::User => vec!["compgen -u".to_owned()],
+    ArgType::Group => vec!["compgen -g".to_owned()],
+    ArgType::Host => vec![
+      "compgen -A hostname".to_owned(),
+      // Bash doesn't look at the SSH config, so get the hosts from there too
+      r#"compgen -W "$(awk 'tolower($1) == "host" { for (i = 2; i <= NF; i++) if ($i !~ /[*?]/) print $i }' ~/.ssh/config 2>/dev/null)""#.to_owned(),
+    ],
+    ArgType::Pid => vec![r#"compgen -W "$(ps -e -o pid=)""#.to_owned()],
+    ArgType::Signal => vec!["compgen -A signal".to_owned()],
+    ArgType::EnvVar => vec!["compgen -e".to_owned()],
+    // /sys/class/net is only on Linux, ifconfig -l works on macOS and BSDs
+    ArgType::NetInterface => vec![
+      r#"compgen -W "$(ls /sys/class/net 2>/dev/null || ifconfig -l 2>/dev/null)""#
+        .to_owned(),
+    ],
+    ArgType::Port => vec![
+      r#"compgen -W "$(awk '$2 ~ /^[0-9]+\// { sub(/\/.*/, "", $2); print $1, $2 }' /etc/services 2>/dev/null)""#
+        .to_owned(),
+    ],
+    ArgType::Strings(strs) => vec![format!(
+      "compgen -W '{}'",
+      strs
+        .iter()
+        .map(|(s, _)| s.replace('\'', ""))
+        .collect::<Vec<_>>()
+        .join(" ")
+    )],
+    ArgType::Run { cmd, sep } => {
+      let cmd = match sep {
+        // Only keep the values, not the descriptions
+        Some(sep) => format!("{cmd} | awk -F '{sep}' '{{print $1}}'"),
+        None => cmd.to_owned(),
+      };
+      vec![format!("compgen -W \"$({cmd})\"")]
+    }
+    // Nothing to complete for these, but they shouldn't fall back to paths
+    ArgType::Int { .. }
+    | ArgType::Float
+    | ArgType::Duration
+    | ArgType::Url
+    | ArgType::Text => vec![],
+    // Lists are split up by complete_values, but lists inside other types
+    // are just completed as a single value
+    ArgType::List { typ, .. } => complete_type(typ),
+    ArgType::Any(types) => types.iter().flat_map(complete_type).collect(),
+  }
+}
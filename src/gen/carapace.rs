@@ -2,8 +2,8 @@ use std::collections::BTreeMap;
 
 use serde::Serialize;
 
-use super::util::{pair_forms, trim_dashes};
-use crate::{ArgType, CommandInfo};
+use super::util::{describe, pair_forms, trim_dashes};
+use crate::{Arg, ArgType, CommandInfo, Flag, ValueCount};
 
 const HEADER: &str =
   "# yaml-language-server: $schema=https://carapace.sh/schemas/command.json";
@@ -12,10 +12,18 @@ const HEADER: &str =
 #[derive(Serialize)]
 struct CarapaceCmd {
   name: String,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  aliases: Vec<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   description: Option<String>,
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  hidden: bool,
   #[serde(skip_serializing_if = "BTreeMap::is_empty")]
   flags: BTreeMap<String, String>,
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  persistentflags: BTreeMap<String, String>,
+  #[serde(rename = "exclusiveflags", skip_serializing_if = "Vec::is_empty")]
+  exclusive_flags: Vec<Vec<String>>,
   #[serde(skip_serializing_if = "Completion::is_empty")]
   completion: Completion,
   #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -26,13 +34,18 @@ struct CarapaceCmd {
 struct Completion {
   #[serde(skip_serializing_if = "Vec::is_empty")]
   positional: Vec<Vec<String>>,
+  /// For the variadic argument at the end, if there is one
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  positionalany: Vec<String>,
   #[serde(skip_serializing_if = "BTreeMap::is_empty")]
   flag: BTreeMap<String, Vec<String>>,
 }
 
 impl Completion {
   fn is_empty(&self) -> bool {
-    self.positional.is_empty() && self.flag.is_empty()
+    self.positional.is_empty()
+      && self.positionalany.is_empty()
+      && self.flag.is_empty()
   }
 }
 
@@ -48,35 +61,119 @@ fn to_carapace(cmd: &CommandInfo) -> CarapaceCmd {
   // BTreeMap used rather than HashMap so that output always has predictable
   // order, otherwise tests can fail sometimes
   let mut flags = BTreeMap::new();
+  let mut persistentflags = BTreeMap::new();
   let mut flag_completions = BTreeMap::new();
 
   for flag in &cmd.flags {
-    let desc = flag.desc.clone().unwrap_or_default();
+    let desc =
+      describe(flag.desc.as_deref(), flag.deprecated).unwrap_or_default();
     let typ = flag.typ.as_ref().map(carapace_type);
 
-    for (short, long) in pair_forms(&flag.forms) {
+    // Carapace can't hide only some forms of a flag, so aliases are completed
+    // like any other form
+    let forms = flag
+      .forms
+      .iter()
+      .chain(&flag.aliases)
+      .cloned()
+      .collect::<Vec<_>>();
+    for (short, long) in pair_forms(&forms) {
       let (main_form, combined) = match (short, long) {
         (Some(short), Some(long)) => (long, format!("{},{}", short, long)),
         (Some(short), None) => (short, short.to_owned()),
         (None, Some(long)) => (long, long.to_owned()),
         (None, None) => unreachable!(),
       };
-      if let Some(typ) = typ.clone() {
-        // If there's an argument, the flag name needs a `=` after it
-        flags.insert(format!("{}=", combined), desc.clone());
-        flag_completions.insert(trim_dashes(main_form), typ);
+      // If there's an argument, the flag name needs a `=` after it, and a `?`
+      // before that if the argument is optional
+      let suffix = match flag.value_count() {
+        ValueCount::Zero => "",
+        ValueCount::Optional => "?=",
+        ValueCount::One | ValueCount::Exactly(_) | ValueCount::Variadic => "=",
+      };
+      // Carapace hides flags once they've been used unless they have a `*`,
+      // and `&` hides them entirely.
+      let repeat = if flag.repeatable { "*" } else { "" };
+      let hidden = if flag.hidden { "&" } else { "" };
+      // Carapace passes persistent flags on to subcommands itself
+      let flags = if flag.persistent {
+        &mut persistentflags
       } else {
-        flags.insert(combined, desc.clone());
+        &mut flags
+      };
+      flags.insert(format!("{combined}{suffix}{repeat}{hidden}"), desc.clone());
+      if let Some(typ) = typ
+        .as_ref()
+        .filter(|typ| !suffix.is_empty() && !typ.is_empty())
+      {
+        flag_completions.insert(trim_dashes(main_form), typ.clone());
       }
     }
   }
 
+  // Carapace only has exclusive groups, so each conflict becomes a group of
+  // two flags. Flags are referred to by their long names if they have any.
+  let flag_name = |flag: &Flag| {
+    let form = flag
+      .forms
+      .iter()
+      .find(|form| form.starts_with("--"))
+      .unwrap_or(&flag.forms[0]);
+    trim_dashes(form)
+  };
+  let mut exclusive_flags = Vec::new();
+  for group in &cmd.exclusive {
+    let mut names = Vec::new();
+    for form in group {
+      if let Some(flag) =
+        cmd.flags.iter().find(|flag| flag.forms.contains(form))
+      {
+        let name = flag_name(flag);
+        if !names.contains(&name) {
+          names.push(name);
+        }
+      }
+    }
+    // Groups with fewer than two flags don't exclude anything
+    if names.len() >= 2 {
+      exclusive_flags.push(names);
+    }
+  }
+  for flag in &cmd.flags {
+    for form in &flag.conflicts {
+      if let Some(other) =
+        cmd.flags.iter().find(|other| other.forms.contains(form))
+      {
+        // Flags that list each other would otherwise give the same pair twice
+        let mut pair = vec![flag_name(flag), flag_name(other)];
+        pair.sort_unstable();
+        if other != flag && !exclusive_flags.contains(&pair) {
+          exclusive_flags.push(pair);
+        }
+      }
+    }
+  }
+
+  let arg_type =
+    |arg: &Arg| arg.typ.as_ref().map(carapace_type).unwrap_or_default();
+  let (positional, positionalany) = match cmd.args.split_last() {
+    Some((last, rest)) if last.variadic => {
+      (rest.iter().map(arg_type).collect(), arg_type(last))
+    }
+    _ => (cmd.args.iter().map(arg_type).collect(), Vec::new()),
+  };
+
   CarapaceCmd {
     name: cmd.name.clone(),
-    description: cmd.desc.clone(),
+    aliases: cmd.aliases.clone(),
+    description: describe(cmd.desc.as_deref(), cmd.deprecated),
+    hidden: cmd.hidden,
     flags,
+    persistentflags,
+    exclusive_flags,
     completion: Completion {
-      positional: cmd.args.iter().map(carapace_type).collect(),
+      positional,
+      positionalany,
       flag: flag_completions,
     },
     commands: cmd.subcommands.iter().map(to_carapace).collect(),
@@ -96,10 +193,58 @@ fn carapace_type(typ: &ArgType) -> Vec<String> {
         }
       })
       .collect::<Vec<_>>(),
-    ArgType::Path => vec!["$files".to_owned()],
+    ArgType::Path | ArgType::Unknown => vec!["$files".to_owned()],
     ArgType::Dir => vec!["$directories".to_owned()],
+    // Carapace can only filter files by their extensions, and always offers
+    // directories
+    ArgType::Glob { globs, .. } => {
+      let exts = globs
+        .iter()
+        .map(|glob| {
+          glob.strip_prefix('*').filter(|ext| {
+            ext.starts_with('.') && !ext.contains(['*', '?', '['])
+          })
+        })
+        .collect::<Option<Vec<_>>>();
+      match exts {
+        Some(exts) => vec![format!("$files([{}])", exts.join(", "))],
+        None => vec!["$files".to_owned()],
+      }
+    }
+    ArgType::CommandName => vec!["$executables".to_owned()],
+    ArgType::Run { cmd, sep } => vec![match sep {
+      // Carapace expects a tab between the value and the description
+      Some(sep) if sep != "\t" => {
+        format!("$({cmd} | awk -F '{sep}' '{{print $1 \"\\t\" $2}}')")
+      }
+      _ => format!("$({cmd})"),
+    }],
+    // These macros come from carapace-bin
+    ArgType::User => vec!["$carapace.os.Users".to_owned()],
+    ArgType::Group => vec!["$carapace.os.Groups".to_owned()],
+    ArgType::Host => vec!["$carapace.net.Hosts".to_owned()],
+    ArgType::Pid => vec!["$carapace.ps.ProcessIds".to_owned()],
+    ArgType::Signal => vec!["$carapace.ps.KillSignals".to_owned()],
+    ArgType::EnvVar => vec!["$carapace.os.EnvironmentVariables".to_owned()],
+    ArgType::NetInterface => vec![
+      "$(ls /sys/class/net 2>/dev/null || ifconfig -l | tr ' ' '\\n')"
+        .to_owned(),
+    ],
+    ArgType::Port => vec!["$carapace.net.Ports".to_owned()],
+    ArgType::Int { .. }
+    | ArgType::Float
+    | ArgType::Duration
+    | ArgType::Url
+    | ArgType::Text => vec![],
+    // Carapace's modifiers apply to all the values before them
+    ArgType::List { typ, sep } => {
+      let mut values = carapace_type(typ);
+      if !values.is_empty() {
+        values.push(format!("$uniquelist({sep})"));
+      }
+      values
+    }
     ArgType::Any(types) => types.iter().flat_map(carapace_type).collect(),
-    _ => todo!(),
   }
 }
 
@@ -109,7 +254,7 @@ mod tests {
   use pretty_assertions::assert_eq;
 
   use super::generate;
-  use crate::{ArgType, CommandInfo, Flag};
+  use crate::{test_util::flag, ArgType, CommandInfo, Flag};
 
   /// Removes the header from the generated YAML and trims both strings
   macro_rules! assert_fmt {
@@ -134,10 +279,27 @@ mod tests {
       "#,
       CommandInfo {
         name: "foo".to_owned(),
-        desc: None,
-        args: vec![],
-        flags: vec![],
-        subcommands: vec![],
+        ..Default::default()
+      }
+    )
+  }
+
+  #[test]
+  fn test_unresolved_exclusive() {
+    assert_fmt!(
+      r#"
+        name: foo
+        flags:
+          --json: Print JSON
+      "#,
+      CommandInfo {
+        name: "foo".to_owned(),
+        flags: vec![flag(&["--json"], Some("Print JSON"))],
+        exclusive: vec![
+          vec!["--json".to_owned(), "--yaml".to_owned()],
+          vec!["--toml".to_owned()],
+        ],
+        ..Default::default()
       }
     )
   }
@@ -167,16 +329,17 @@ mod tests {
       CommandInfo {
         name: "foo".to_owned(),
         desc: Some("blah blah\nNewline".to_owned()),
-        args: vec![ArgType::Any(vec![ArgType::Path])],
+        args: vec![ArgType::Any(vec![ArgType::Path]).into()],
         flags: vec![Flag {
           forms: vec!["-b".to_owned(), "--bar".to_owned(), "--why".to_owned()],
           desc: Some("This flag does nothing".to_owned()),
           typ: Some(ArgType::Strings(vec![
             ("baz1".to_owned(), Some("Description for baz1".to_owned())),
             ("baz2".to_owned(), Some("Another description".to_owned()))
-          ]))
+          ])),
+          ..Default::default()
         }],
-        subcommands: vec![],
+        ..Default::default()
       }
     )
   }
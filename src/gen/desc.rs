@@ -0,0 +1,170 @@
+//! For shortening descriptions so they fit in completion menus
+//!
+//! Partly ported from the description-truncating part of Fish's
+//! `create_manpage_completions.py`
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Added to the end of descriptions that had to be cut off
+static ELLIPSIS: &str = "...";
+
+/// Words that end with a period but don't end a sentence
+static ABBREVIATIONS: &[&str] = &[
+  "e.g.", "i.e.", "etc.", "vs.", "cf.", "approx.", "incl.", "resp.", "esp.",
+  "viz.",
+];
+
+/// Shorten a description so that its display width is at most `max_len`
+///
+/// If it's too long, as many whole sentences as will fit are kept. If even
+/// the first sentence doesn't fit, the description is cut off at a word
+/// boundary and [`ELLIPSIS`] is added. A `max_len` of 0 means descriptions
+/// aren't shortened at all.
+#[must_use]
+pub fn shorten(desc: &str, max_len: usize) -> String {
+  if max_len == 0 || desc.width() <= max_len {
+    return desc.to_owned();
+  }
+
+  let sentences = sentence_ends(desc)
+    .into_iter()
+    .map(|end| desc[..end].trim_end())
+    .take_while(|sentence| sentence.width() <= max_len)
+    .last();
+  if let Some(sentences) = sentences {
+    return sentences.to_owned();
+  }
+
+  truncate_words(desc, max_len)
+}
+
+/// Find the byte indices right after each sentence-ending punctuation mark
+fn sentence_ends(text: &str) -> Vec<usize> {
+  let mut ends = Vec::new();
+  let mut chars = text.char_indices().peekable();
+  // Where the current word started
+  let mut word_start = 0;
+
+  while let Some((i, c)) = chars.next() {
+    let next = chars.peek().map(|&(_, next)| next);
+    if c.is_whitespace() {
+      word_start = i + c.len_utf8();
+    } else if matches!(c, '.' | '!' | '?')
+      && next.is_none_or(char::is_whitespace)
+    {
+      let end = i + c.len_utf8();
+      let original = text[word_start..end]
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+      let word = original.to_lowercase();
+      let word = word.as_str();
+      // Initials like the `A.` in `A. Person` count as abbreviations too, as
+      // does `No.` when it's followed by a number
+      let is_initial = original.len() == 2
+        && original.starts_with(|c: char| c.is_ascii_uppercase());
+      let is_abbrev = c == '.'
+        && (ABBREVIATIONS.contains(&word)
+          || is_initial
+          || (word == "no."
+            && text[end..]
+              .trim_start()
+              .starts_with(|c: char| c.is_ascii_digit())));
+      if !is_abbrev {
+        ends.push(end);
+      }
+    }
+  }
+
+  ends
+}
+
+/// Cut off a description at a word boundary so that it fits in `max_len`
+/// along with the [`ELLIPSIS`]
+fn truncate_words(desc: &str, max_len: usize) -> String {
+  let max_len = max_len.saturating_sub(ELLIPSIS.width());
+
+  // The index of the end of the text that fits
+  let mut end = 0;
+  // The end of the last whole word that fits
+  let mut last_word_end = None;
+  let mut width = 0;
+  for (i, c) in desc.char_indices() {
+    width += c.width().unwrap_or(0);
+    if width > max_len {
+      break;
+    }
+    if c.is_whitespace() {
+      last_word_end = Some(i);
+    }
+    end = i + c.len_utf8();
+  }
+
+  let end = match last_word_end {
+    // If the next character's a space, the last word fit completely
+    _ if desc[end..].starts_with(char::is_whitespace) => end,
+    Some(word_end) => word_end,
+    None => end,
+  };
+  let text = desc[..end].trim_end_matches(|c: char| {
+    c.is_whitespace() || matches!(c, ',' | ';' | ':')
+  });
+  format!("{text}{ELLIPSIS}")
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::shorten;
+
+  #[test]
+  fn test_short_enough() {
+    assert_eq!("Foo bar. Baz.", shorten("Foo bar. Baz.", 13));
+    assert_eq!("Foo bar. Baz.", shorten("Foo bar. Baz.", 0));
+  }
+
+  #[test]
+  fn test_sentences() {
+    assert_eq!(
+      "Use a thing, e.g. foo.",
+      shorten("Use a thing, e.g. foo. This is another sentence.", 30)
+    );
+  }
+
+  #[test]
+  fn test_no() {
+    assert_eq!(
+      "Answer yes or no.",
+      shorten("Answer yes or no. Defaults to yes.", 25)
+    );
+    assert_eq!(
+      "Use port no. 80 by default.",
+      shorten("Use port no. 80 by default. Change it with -p.", 30)
+    );
+  }
+
+  #[test]
+  fn test_initials() {
+    assert_eq!(
+      "Written by J. Doe.",
+      shorten("Written by J. Doe. See the docs.", 25)
+    );
+    assert_eq!("Do step 2.", shorten("Do step 2. Next, do step 3.", 15));
+    assert_eq!("Use v.", shorten("Use v. Then do w.", 10));
+  }
+
+  #[test]
+  fn test_words() {
+    assert_eq!(
+      "Do the thing to...",
+      shorten("Do the thing to the other thing", 20)
+    );
+    assert_eq!("Foobarb...", shorten("Foobarbazquux", 10));
+  }
+
+  #[test]
+  fn test_unicode() {
+    assert_eq!("Café — très...", shorten("Café — très bien, merci", 16));
+    // Wide characters take up two columns each
+    assert_eq!("日本...", shorten("日本語のテキスト", 7));
+  }
+}
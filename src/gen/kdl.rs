@@ -1,12 +1,34 @@
 use kdl::{KdlDocument, KdlEntry, KdlNode};
 
-use crate::gen::CommandInfo;
+use crate::{gen::CommandInfo, Arg, ArgStyle, ArgType, FlagArg, ValueCount};
 
 /// Turn a [`CommandInfo`] into a [`KdlNode`]
 pub fn to_kdl_node(cmd: &CommandInfo) -> KdlNode {
   let mut node = KdlNode::new(cmd.name.to_string());
+  if cmd.hidden {
+    node.insert("hidden", true);
+  }
+  if cmd.deprecated {
+    node.insert("deprecated", true);
+  }
   let mut children = KdlDocument::new();
 
+  if let Some(desc) = &cmd.desc {
+    let mut desc_node = KdlNode::new("desc");
+    desc_node.entries_mut().push(KdlEntry::new(desc.as_str()));
+    children.nodes_mut().push(desc_node);
+  }
+
+  if !cmd.aliases.is_empty() {
+    let mut aliases_node = KdlNode::new("aliases");
+    for alias in &cmd.aliases {
+      aliases_node
+        .entries_mut()
+        .push(KdlEntry::new(alias.as_str()));
+    }
+    children.nodes_mut().push(aliases_node);
+  }
+
   let mut flag_nodes = KdlDocument::new();
 
   for flag in &cmd.flags {
@@ -17,14 +39,52 @@ pub fn to_kdl_node(cmd: &CommandInfo) -> KdlNode {
       flag_node.entries_mut().push(KdlEntry::new(form.as_str()));
     }
 
+    if flag.repeatable {
+      flag_node.insert("repeatable", true);
+    }
+    if flag.persistent {
+      flag_node.insert("persistent", true);
+    }
+    if flag.hidden {
+      flag_node.insert("hidden", true);
+    }
+    if flag.deprecated {
+      flag_node.insert("deprecated", true);
+    }
+
+    let mut flag_children = KdlDocument::new();
+
     if let Some(desc) = &flag.desc {
       let mut description_node = KdlNode::new("desc");
       description_node
         .entries_mut()
         .push(KdlEntry::new(desc.as_str()));
-
-      let mut flag_children = KdlDocument::new();
       flag_children.nodes_mut().push(description_node);
+    }
+
+    if let Some(typ) = &flag.typ {
+      flag_children.nodes_mut().push(to_type_node(typ));
+    }
+
+    if let Some(arg) = &flag.arg {
+      flag_children.nodes_mut().push(to_arg_node(arg));
+    }
+
+    for (name, forms) in [
+      ("aliases", &flag.aliases),
+      ("requires", &flag.requires),
+      ("conflicts", &flag.conflicts),
+    ] {
+      if !forms.is_empty() {
+        let mut forms_node = KdlNode::new(name);
+        for form in forms {
+          forms_node.entries_mut().push(KdlEntry::new(form.as_str()));
+        }
+        flag_children.nodes_mut().push(forms_node);
+      }
+    }
+
+    if !flag_children.nodes().is_empty() {
       flag_node.set_children(flag_children);
     }
 
@@ -35,6 +95,36 @@ pub fn to_kdl_node(cmd: &CommandInfo) -> KdlNode {
   flags.set_children(flag_nodes);
   children.nodes_mut().push(flags);
 
+  if !cmd.args.is_empty() {
+    let mut args = KdlDocument::new();
+    for arg in &cmd.args {
+      args.nodes_mut().push(to_positional_node(arg));
+    }
+
+    let mut args_node = KdlNode::new("args");
+    args_node.set_children(args);
+    children.nodes_mut().push(args_node);
+  }
+
+  if !cmd.exclusive.is_empty() {
+    let mut groups = KdlDocument::new();
+    for group in &cmd.exclusive {
+      let mut forms = group.iter();
+      let Some(first) = forms.next() else {
+        continue;
+      };
+      let mut group_node = KdlNode::new(first.as_str());
+      for form in forms {
+        group_node.entries_mut().push(KdlEntry::new(form.as_str()));
+      }
+      groups.nodes_mut().push(group_node);
+    }
+
+    let mut exclusive_node = KdlNode::new("exclusive");
+    exclusive_node.set_children(groups);
+    children.nodes_mut().push(exclusive_node);
+  }
+
   if !cmd.subcommands.is_empty() {
     let mut subcommands = KdlDocument::new();
     for subcmd in &cmd.subcommands {
@@ -49,3 +139,150 @@ pub fn to_kdl_node(cmd: &CommandInfo) -> KdlNode {
   node.set_children(children);
   node
 }
+
+/// Turn a positional argument into a node inside `args`. Unnamed, required
+/// arguments with a single type are written as just that type, e.g. `path`.
+fn to_positional_node(arg: &Arg) -> KdlNode {
+  if let Arg {
+    name: None,
+    desc: None,
+    typ: Some(typ),
+    optional: false,
+    variadic: false,
+  } = arg
+  {
+    if let [node] = type_nodes(typ).as_slice() {
+      return node.clone();
+    }
+  }
+
+  let mut node = KdlNode::new("arg");
+  if let Some(name) = &arg.name {
+    node.entries_mut().push(KdlEntry::new(name.as_str()));
+  }
+  if arg.optional {
+    node.insert("optional", true);
+  }
+  if arg.variadic {
+    node.insert("variadic", true);
+  }
+
+  let mut children = KdlDocument::new();
+  if let Some(desc) = &arg.desc {
+    let mut desc_node = KdlNode::new("desc");
+    desc_node.entries_mut().push(KdlEntry::new(desc.as_str()));
+    children.nodes_mut().push(desc_node);
+  }
+  if let Some(typ) = &arg.typ {
+    children.nodes_mut().push(to_type_node(typ));
+  }
+  if !children.nodes().is_empty() {
+    node.set_children(children);
+  }
+  node
+}
+
+/// Turn a type into a `type` node, e.g. `type { path; dir }`
+fn to_type_node(typ: &ArgType) -> KdlNode {
+  let mut types = KdlDocument::new();
+  types.nodes_mut().extend(type_nodes(typ));
+  let mut node = KdlNode::new("type");
+  node.set_children(types);
+  node
+}
+
+/// The nodes that go inside a `type` node. There's more than one if the type
+/// is [`ArgType::Any`].
+fn type_nodes(typ: &ArgType) -> Vec<KdlNode> {
+  match typ {
+    ArgType::Path => vec![KdlNode::new("path")],
+    ArgType::Dir => vec![KdlNode::new("dir")],
+    ArgType::Glob { globs, dirs } => {
+      let mut node = KdlNode::new("path");
+      for glob in globs {
+        node.entries_mut().push(KdlEntry::new(glob.as_str()));
+      }
+      if !dirs {
+        node.insert("dirs", false);
+      }
+      vec![node]
+    }
+    ArgType::CommandName => vec![KdlNode::new("command")],
+    ArgType::User => vec![KdlNode::new("user")],
+    ArgType::Group => vec![KdlNode::new("group")],
+    ArgType::Host => vec![KdlNode::new("host")],
+    ArgType::Pid => vec![KdlNode::new("pid")],
+    ArgType::Signal => vec![KdlNode::new("signal")],
+    ArgType::EnvVar => vec![KdlNode::new("env-var")],
+    ArgType::NetInterface => vec![KdlNode::new("interface")],
+    ArgType::Port => vec![KdlNode::new("port")],
+    ArgType::Int { min, max } => {
+      let mut node = KdlNode::new("int");
+      if let Some(min) = min {
+        node.insert("min", *min);
+      }
+      if let Some(max) = max {
+        node.insert("max", *max);
+      }
+      vec![node]
+    }
+    ArgType::List { typ, sep } => {
+      let mut types = KdlDocument::new();
+      types.nodes_mut().extend(type_nodes(typ));
+      let mut node = KdlNode::new("list");
+      if sep != "," {
+        node.insert("sep", sep.as_str());
+      }
+      node.set_children(types);
+      vec![node]
+    }
+    ArgType::Float => vec![KdlNode::new("float")],
+    ArgType::Duration => vec![KdlNode::new("duration")],
+    ArgType::Url => vec![KdlNode::new("url")],
+    ArgType::Text => vec![KdlNode::new("string")],
+    ArgType::Unknown => vec![KdlNode::new("unknown")],
+    ArgType::Run { cmd, sep } => {
+      let mut node = KdlNode::new("run");
+      node.entries_mut().push(KdlEntry::new(cmd.as_str()));
+      if let Some(sep) = sep {
+        node.insert("sep", sep.as_str());
+      }
+      vec![node]
+    }
+    ArgType::Strings(strs) => {
+      let mut values = KdlDocument::new();
+      for (value, desc) in strs {
+        let mut value_node = KdlNode::new(value.as_str());
+        if let Some(desc) = desc {
+          value_node.entries_mut().push(KdlEntry::new(desc.as_str()));
+        }
+        values.nodes_mut().push(value_node);
+      }
+      let mut node = KdlNode::new("strings");
+      node.set_children(values);
+      vec![node]
+    }
+    ArgType::Any(types) => types.iter().flat_map(type_nodes).collect(),
+  }
+}
+
+/// Turn a flag's argument into an `arg` node, e.g. `arg "FILE" count=2`
+fn to_arg_node(arg: &FlagArg) -> KdlNode {
+  let mut node = KdlNode::new("arg");
+  if let Some(name) = &arg.name {
+    node.entries_mut().push(KdlEntry::new(name.as_str()));
+  }
+  match arg.count {
+    ValueCount::One => {}
+    ValueCount::Exactly(n) => {
+      node.insert("count", n as i64);
+    }
+    count => {
+      node.insert("count", count.to_string());
+    }
+  }
+  if arg.style != ArgStyle::Any {
+    node.insert("style", arg.style.to_string());
+  }
+  node
+}
@@ -1,5 +1,6 @@
 mod bash;
 mod carapace;
+mod desc;
 mod kdl;
 mod nu;
 mod util;
@@ -8,17 +9,16 @@ mod zsh;
 use std::{fs, path::Path};
 
 use clap::ValueEnum;
+use serde::Serialize;
 
+pub use self::desc::shorten;
 use self::kdl::to_kdl_node;
-use crate::{CommandInfo, Flag};
+use crate::{parse_deser::schema::SCHEMA_URL, CommandInfo, Flag};
 
-/// Maximum length of a description
-///
-/// After this, `...` will be added
-static MAX_DESC_LEN: usize = 80;
-static ELLIPSIS: &str = "...";
+/// Default maximum length of a description
+pub const MAX_DESC_LEN: usize = 80;
 
-#[derive(Debug, Copy, Clone, ValueEnum)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
 pub enum OutputFormat {
   /// Generate completions for Zsh
   Zsh,
@@ -36,6 +36,10 @@ pub enum OutputFormat {
 
 /// Generate completion for the given shell and write to a file
 ///
+/// Descriptions longer than `max_desc_len` are shortened (see [`shorten`]).
+/// The file is only written to if its contents would change. Returns whether
+/// or not the file was written to.
+///
 /// # Errors
 ///
 /// Fails if it can't write to a file, or if serde can't serialize the command
@@ -43,22 +47,42 @@ pub enum OutputFormat {
 pub fn generate_to_file(
   cmd: &CommandInfo,
   format: OutputFormat,
+  max_desc_len: usize,
   out_dir: impl AsRef<Path>,
-) -> std::io::Result<()> {
+) -> std::io::Result<bool> {
   let out_dir = out_dir.as_ref();
-  let (file_name, text) = generate(cmd, format);
-  fs::write(out_dir.join(file_name), text)
+  let (file_name, text) = generate(cmd, format, max_desc_len);
+  let path = out_dir.join(file_name);
+  if fs::read_to_string(&path).is_ok_and(|old| old == text) {
+    Ok(false)
+  } else {
+    fs::write(path, text)?;
+    Ok(true)
+  }
 }
 
 /// Generate completion for the given shell as a string
+///
+/// Descriptions longer than `max_desc_len` are shortened (see [`shorten`]).
 #[must_use]
-pub fn generate_to_str(cmd: &CommandInfo, format: OutputFormat) -> String {
-  let (_, text) = generate(cmd, format);
+pub fn generate_to_str(
+  cmd: &CommandInfo,
+  format: OutputFormat,
+  max_desc_len: usize,
+) -> String {
+  let (_, text) = generate(cmd, format, max_desc_len);
   text
 }
 
-fn generate(cmd: &CommandInfo, format: OutputFormat) -> (String, String) {
-  let cmd = preprocess(cmd);
+fn generate(
+  cmd: &CommandInfo,
+  format: OutputFormat,
+  max_desc_len: usize,
+) -> (String, String) {
+  // Carapace and Zsh completions share persistent flags with subcommands
+  // themselves, and KDL and JSON are kept as they were written
+  let inherit = matches!(format, OutputFormat::Bash | OutputFormat::Nu);
+  let cmd = preprocess(cmd, max_desc_len, inherit.then_some(&[]));
   match format {
     OutputFormat::Bash => bash::generate(&cmd),
     OutputFormat::Zsh => zsh::generate(&cmd),
@@ -68,8 +92,11 @@ fn generate(cmd: &CommandInfo, format: OutputFormat) -> (String, String) {
     }
     OutputFormat::Json => (
       format!("{}.json", cmd.name),
-      serde_json::to_string(&cmd)
-        .expect("Command info should've been serialized to JSON"),
+      serde_json::to_string(&JsonOutput {
+        schema: SCHEMA_URL,
+        cmd: &cmd,
+      })
+      .expect("Command info should've been serialized to JSON"),
     ),
     OutputFormat::Carapace => {
       (format!("{}.yaml", cmd.name), carapace::generate(&cmd))
@@ -77,35 +104,60 @@ fn generate(cmd: &CommandInfo, format: OutputFormat) -> (String, String) {
   }
 }
 
-/// Trim descriptions
-/// todo pass the max description length as an option
-/// possibly have each generator do the trimming separately
-fn preprocess(cmd: &CommandInfo) -> CommandInfo {
-  let flags: Vec<Flag> = cmd
+/// JSON output, with a link to the schema so editors can check it
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+  #[serde(rename = "$schema")]
+  schema: &'static str,
+  #[serde(flatten)]
+  cmd: &'a CommandInfo,
+}
+
+/// Shorten descriptions, and copy persistent flags into subcommands if
+/// `inherited` is given. That's for shells without their own way to inherit
+/// flags, and `inherited` is the persistent flags of the command's ancestors.
+/// A subcommand's own flags take precedence over inherited ones.
+fn preprocess(
+  cmd: &CommandInfo,
+  max_desc_len: usize,
+  inherited: Option<&[Flag]>,
+) -> CommandInfo {
+  let mut flags: Vec<Flag> = cmd
     .flags
     .iter()
-    .map(|flag| {
-      // TODO port the sentence-splitting part too
-      // https://github.com/fish-shell/fish-shell/blob/master/share/tools/create_manpage_completions.py#L211
-      let desc = flag.desc.as_ref().map(|desc| {
-        if desc.len() > MAX_DESC_LEN {
-          format!("{}{}", &desc[0..MAX_DESC_LEN - ELLIPSIS.len()], ELLIPSIS)
-        } else {
-          desc.to_owned()
-        }
-      });
-      Flag {
-        forms: flag.forms.clone(),
-        desc,
-        typ: flag.typ.clone(),
-      }
+    .map(|flag| Flag {
+      desc: flag.desc.as_ref().map(|desc| shorten(desc, max_desc_len)),
+      ..flag.clone()
     })
     .collect();
+  for flag in inherited.unwrap_or_default() {
+    let overridden = flags
+      .iter()
+      .any(|own| own.forms.iter().any(|form| flag.forms.contains(form)));
+    if !overridden {
+      flags.push(flag.clone());
+    }
+  }
+  let sub_inherited = inherited.map(|_| {
+    flags
+      .iter()
+      .filter(|flag| flag.persistent)
+      .cloned()
+      .collect::<Vec<_>>()
+  });
   CommandInfo {
     name: cmd.name.clone(),
-    desc: cmd.desc.clone(),
+    desc: cmd.desc.as_ref().map(|desc| shorten(desc, max_desc_len)),
     flags,
     args: cmd.args.clone(),
-    subcommands: cmd.subcommands.iter().map(preprocess).collect(),
+    subcommands: cmd
+      .subcommands
+      .iter()
+      .map(|subcmd| preprocess(subcmd, max_desc_len, sub_inherited.as_deref()))
+      .collect(),
+    exclusive: cmd.exclusive.clone(),
+    aliases: cmd.aliases.clone(),
+    hidden: cmd.hidden,
+    deprecated: cmd.deprecated,
   }
 }
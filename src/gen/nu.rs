@@ -1,8 +1,11 @@
 use regex::Regex;
 
 use crate::{
-  gen::{util::Output, CommandInfo},
-  ArgType,
+  gen::{
+    util::{self, Output},
+    CommandInfo,
+  },
+  ArgType, ValueCount,
 };
 
 /// Generate completions for Nushell
@@ -20,13 +23,19 @@ fn generate_cmd(cmd_name: &str, cmd: &CommandInfo, out: &mut Output) {
   // complete flags first, then the actual export extern, so that the extern's
   // signature can use the `nu-complete` command for completing flags
   let mut flags_strs = vec![];
-  // Flags that will need a nu-complete function to complete them
+  // Flags and arguments that will need a nu-complete function to complete
+  // them
   let mut complicated_flags = Vec::new();
-  for flag in &cmd.flags {
+  // Nu completes flag names itself from the extern's signature, with no hook
+  // for custom completers, so flags can't be filtered using `conflicts` or
+  // `exclusive`. For the same reason, hidden flags can't be completed only once
+  // they've been typed exactly, so they're left out entirely
+  for flag in cmd.flags.iter().filter(|flag| !flag.hidden) {
     // Filter out flags that Nu won't like
     let forms = flag
       .forms
       .iter()
+      .chain(&flag.aliases)
       .filter(|form| {
         (form.starts_with("--") || form.len() == 2)
           && (valid_flag.is_match(form))
@@ -36,15 +45,22 @@ fn generate_cmd(cmd_name: &str, cmd: &CommandInfo, out: &mut Output) {
     let (short_forms, long_forms): (Vec<_>, Vec<_>) =
       forms.iter().partition(|f| f.len() == 2);
 
-    let desc_str = if let Some(desc) = &flag.desc {
+    let desc_str = if let Some(desc) =
+      util::describe(flag.desc.as_deref(), flag.deprecated)
+    {
       format!(" # {desc}")
     } else {
       String::new()
     };
 
-    let type_str = if let Some(typ) = flag.typ.as_ref() {
-      if typ == &ArgType::Unknown {
-        ": string".to_owned()
+    // Nu flags can't have optional values, so those flags are made switches
+    let takes_value =
+      !matches!(flag.value_count(), ValueCount::Zero | ValueCount::Optional);
+    let type_str = if !takes_value {
+      String::new()
+    } else if let Some(typ) = flag.typ.as_ref() {
+      if let Some(nu_type) = plain_type(typ) {
+        format!(": {nu_type}")
       } else {
         // Turn it into a valid Nu identifier
         let first_form = if forms[0].starts_with("--") {
@@ -58,12 +74,12 @@ fn generate_cmd(cmd_name: &str, cmd: &CommandInfo, out: &mut Output) {
         // that's unlikely
         let first_form = first_form.replace('-', "_");
         let res =
-          format!(r#": string@"nu-complete {} {}""#, cmd_name, &first_form);
+          format!(r#": string@"nu-complete {} {}""#, cmd_name, first_form);
         complicated_flags.push((first_form, typ));
         res
       }
     } else {
-      String::new()
+      ": string".to_owned()
     };
 
     // Pair off as many long and short forms as possible
@@ -86,11 +102,56 @@ fn generate_cmd(cmd_name: &str, cmd: &CommandInfo, out: &mut Output) {
     }
   }
 
-  // Generate functions to complete the more complicated flags. The flag to
-  // complete is the last part of the command name rather than an argument.
+  for (i, arg) in cmd.args.iter().enumerate() {
+    // Turn the argument's name into a valid Nu identifier
+    let name = arg
+      .name
+      .as_deref()
+      .map(|name| {
+        name
+          .to_lowercase()
+          .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+      })
+      .filter(|name| !name.is_empty())
+      .unwrap_or_else(|| format!("arg{}", i + 1));
+    let type_str = match &arg.typ {
+      None => "path".to_owned(),
+      Some(ArgType::CommandName) => "string".to_owned(),
+      Some(typ) => match plain_type(typ) {
+        Some(nu_type) => nu_type.to_owned(),
+        None => {
+          let res = format!(r#"string@"nu-complete {cmd_name} {name}""#);
+          complicated_flags.push((name.clone(), typ));
+          res
+        }
+      },
+    };
+    let (prefix, suffix) = if arg.variadic {
+      ("...", "")
+    } else if arg.optional {
+      ("", "?")
+    } else {
+      ("", "")
+    };
+    let desc_str = if let Some(desc) = &arg.desc {
+      format!(" # {desc}")
+    } else {
+      String::new()
+    };
+    flags_strs.push(format!("{prefix}{name}{suffix}: {type_str}{desc_str}"));
+  }
+
+  // Generate functions to complete the more complicated flags and arguments.
+  // The flag to complete is the last part of the command name rather than an
+  // argument.
 
   for (flag, typ) in complicated_flags {
-    out.writeln(format!(r#"def "nu-complete {} {}" [] {{"#, cmd_name, flag));
+    // Lists need to know what's already been typed
+    let params = if has_list(typ) { "context: string" } else { "" };
+    out.writeln(format!(
+      r#"def "nu-complete {} {}" [{}] {{"#,
+      cmd_name, flag, params
+    ));
     out.indent();
     out.writeln(complete_type(typ));
     out.dedent();
@@ -99,7 +160,7 @@ fn generate_cmd(cmd_name: &str, cmd: &CommandInfo, out: &mut Output) {
   }
 
   // Generate the actual `export extern` command
-  if let Some(desc) = cmd.desc.as_ref() {
+  if let Some(desc) = util::describe(cmd.desc.as_deref(), cmd.deprecated) {
     for line in desc.lines() {
       out.writeln(format!("# {}", line));
     }
@@ -111,18 +172,44 @@ fn generate_cmd(cmd_name: &str, cmd: &CommandInfo, out: &mut Output) {
   out.writeln("]");
   out.writeln("");
 
-  for sub_cmd in &cmd.subcommands {
+  // Nu has no way to alias an extern, and giving each alias its own extern
+  // would list it alongside the command it's for, so aliases aren't completed
+  for sub_cmd in cmd.subcommands.iter().filter(|sub_cmd| !sub_cmd.hidden) {
     generate_cmd(&format!("{cmd_name} {}", sub_cmd.name), sub_cmd, out);
   }
 }
 
+/// Whether completing the type involves a list, either directly or inside an
+/// [`ArgType::Any`]
+fn has_list(typ: &ArgType) -> bool {
+  match typ {
+    ArgType::List { .. } => true,
+    ArgType::Any(types) => types.iter().any(has_list),
+    _ => false,
+  }
+}
+
+/// The Nu type to use for types that Nu completes itself or that have nothing
+/// to complete. Durations aren't Nu `duration`s because those are written like
+/// `30sec`
+fn plain_type(typ: &ArgType) -> Option<&'static str> {
+  match typ {
+    ArgType::Path => Some("path"),
+    ArgType::Dir => Some("directory"),
+    ArgType::Unknown => Some("string"),
+    ArgType::Int { .. } => Some("int"),
+    ArgType::Float => Some("number"),
+    ArgType::Duration | ArgType::Url | ArgType::Text => Some("string"),
+    _ => None,
+  }
+}
+
 /// Generate Nu code to provide completions for a particular type.
 ///
 /// Generates a list of records with a value field and possibly a description
 /// field
 fn complete_type(typ: &ArgType) -> String {
   match typ {
-    ArgType::Unknown => complete_type(&ArgType::Path),
     ArgType::Run { cmd, sep: desc_sep } => {
       if let Some(sep) = desc_sep {
         format!(
@@ -149,6 +236,11 @@ fn complete_type(typ: &ArgType) -> String {
           .join(", ")
       )
     }
+    // Put the values already in the list in front of each completion
+    ArgType::List { typ, sep } => format!(
+      "({} | update value {{ |it| ($context | split row ' ' | last | split row '{sep}' | drop | each {{ |v| $v + '{sep}' }} | str join) + $it.value }})",
+      complete_type(typ)
+    ),
     ArgType::Any(types) => format!(
       "[{}]",
       types
@@ -157,6 +249,67 @@ fn complete_type(typ: &ArgType) -> String {
         .collect::<Vec<_>>()
         .join(" ")
     ),
+    ArgType::Glob { globs, dirs } => {
+      let glob = if let [glob] = globs.as_slice() {
+        glob.to_owned()
+      } else {
+        format!("{{{}}}", globs.join(","))
+      };
+      let dirs = if *dirs {
+        " ...(glob '*' --no-file)"
+      } else {
+        ""
+      };
+      format!(
+        "([...(glob '{glob}' --no-dir){dirs}] | path relative-to $env.PWD | each {{ |it| {{value: $it}} }})"
+      )
+    }
+    // These are only used inside lists and unions, since a plain path or
+    // directory uses Nu's own completion
+    ArgType::Path => "(ls | each { |it| {value: $it.name} })".to_owned(),
+    ArgType::Dir => {
+      "(ls | where type == dir | each { |it| {value: $it.name} })".to_owned()
+    }
+    ArgType::User => complete_colon_file("/etc/passwd"),
+    ArgType::Group => complete_colon_file("/etc/group"),
+    ArgType::Host => {
+      r"(open /etc/hosts | lines | parse -r '^\s*[^#\s]+\s+(?P<value>[^#\s]+)')"
+        .to_owned()
+    }
+    ArgType::Pid => {
+      "(ps | each { |it| {value: ($it.pid | into string), description: $it.name} })"
+        .to_owned()
+    }
+    ArgType::Signal => format!(
+      "[{}]",
+      SIGNALS
+        .iter()
+        .map(|sig| format!("{{value: '{sig}'}}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+    ),
+    ArgType::EnvVar => "($env | columns | each { |it| {value: $it} })".to_owned(),
+    ArgType::NetInterface => {
+      "(sys net | each { |it| {value: $it.name} })".to_owned()
+    }
+    ArgType::Port => {
+      r"(open /etc/services | lines | parse -r '^(?P<description>[^#\s]+)\s+(?P<value>\d+)/' | uniq-by value)"
+        .to_owned()
+    }
     _ => "[]".to_owned(), // todo implement
   }
 }
+
+/// Complete with the first field of each line in a colon-separated file like
+/// `/etc/passwd`, skipping comments
+fn complete_colon_file(file: &str) -> String {
+  format!("(open {file} | lines | where $it !~ '^#' | parse '{{value}}:{{rest}}' | select value)")
+}
+
+/// Signals to complete, since Nu can't list them itself
+const SIGNALS: &[&str] = &[
+  "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1",
+  "SEGV", "USR2", "PIPE", "ALRM", "TERM", "CHLD", "CONT", "STOP", "TSTP",
+  "TTIN", "TTOU", "URG", "XCPU", "XFSZ", "VTALRM", "PROF", "WINCH", "IO",
+  "SYS",
+];
@@ -1,3 +1,40 @@
+use crate::{CommandInfo, Flag};
+
+/// Find the other flags that can't be used along with `flag`, either because
+/// they're in an exclusive group together or because one conflicts with the
+/// other. Flags are returned in the order the command has them in.
+pub fn conflicting_flags<'a>(
+  cmd: &'a CommandInfo,
+  flag: &Flag,
+) -> Vec<&'a Flag> {
+  let refers_to = |forms: &[String], other: &Flag| {
+    other.forms.iter().any(|f| forms.contains(f))
+  };
+  cmd
+    .flags
+    .iter()
+    .filter(|other| other.forms != flag.forms)
+    .filter(|other| {
+      refers_to(&flag.conflicts, other)
+        || refers_to(&other.conflicts, flag)
+        || cmd
+          .exclusive
+          .iter()
+          .any(|group| refers_to(group, flag) && refers_to(group, other))
+    })
+    .collect()
+}
+
+/// The description to show for a command or flag, with a note at the start if
+/// it's deprecated
+pub fn describe(desc: Option<&str>, deprecated: bool) -> Option<String> {
+  match (desc, deprecated) {
+    (Some(desc), true) => Some(format!("(deprecated) {desc}")),
+    (None, true) => Some("(deprecated)".to_owned()),
+    (desc, false) => desc.map(str::to_owned),
+  }
+}
+
 /// Wrap in single quotes (and escape single quotes inside) so that it's safe
 /// for Bash and Zsh to read
 pub fn quote_bash(s: impl AsRef<str>) -> String {
@@ -1,6 +1,9 @@
-use crate::gen::{
-  util::{self, Output},
-  CommandInfo,
+use crate::{
+  gen::{
+    util::{self, Output},
+    CommandInfo,
+  },
+  Arg, ArgStyle, ArgType, Flag, ValueCount,
 };
 
 /// Generate a completion file for Zsh
@@ -39,7 +42,7 @@ pub fn generate(cmd: &CommandInfo) -> (String, String) {
   let comp_name = format!("_{}", cmd.name);
   let mut res = Output::new(String::from("\t"));
   res.writeln(format!("#compdef {}", cmd.name));
-  generate_fn(cmd, &mut res, &comp_name);
+  generate_fn(cmd, &mut res, &comp_name, false);
   res.writeln("");
   res.writeln(format!(r#"{comp_name} "$@""#));
   (format!("{comp_name}.zsh"), res.text())
@@ -51,11 +54,71 @@ pub fn generate(cmd: &CommandInfo) -> (String, String) {
 /// * `fn` - What to name the completion function. If you have a command `foo`
 ///   with subcommand `bar`, the completion function for `foo bar` would be
 ///   named `_foo_bar`
-fn generate_fn(cmd: &CommandInfo, out: &mut Output, fn_name: &str) {
+/// * `inherits` - Whether some ancestor of this command has persistent flags,
+///   in which case they're in the `persistent` array
+fn generate_fn(
+  cmd: &CommandInfo,
+  out: &mut Output,
+  fn_name: &str,
+  inherits: bool,
+) {
+  let own_persistent = cmd
+    .flags
+    .iter()
+    .filter(|flag| flag.persistent)
+    .collect::<Vec<_>>();
+  let has_persistent = inherits || !own_persistent.is_empty();
+
   out.writeln("");
   out.writeln(format!("function {fn_name} {{"));
   out.indent();
 
+  // Persistent flags are collected in an array that subcommands' functions
+  // can see, since Zsh functions share their callers' local variables
+  if !own_persistent.is_empty() {
+    if inherits {
+      out.writeln(r#"local -a persistent=("${persistent[@]}")"#);
+    } else {
+      out.writeln("local -a persistent");
+    }
+    out.writeln(format!("{fn_name}_persistent_flags"));
+  }
+
+  // Hidden flags and aliases are only given to `_arguments` once the user
+  // has typed the start of them (more than just dashes)
+  let (specs, hidden_specs) =
+    flag_specs(cmd, cmd.flags.iter().filter(|flag| !flag.persistent));
+  if !hidden_specs.is_empty() {
+    out.writeln("local -a hidden");
+    write_hidden(out, "hidden", &hidden_specs);
+  }
+
+  // Same for hidden subcommands and aliases of subcommands
+  let (hidden_subcmds, visible_subcmds): (Vec<_>, Vec<_>) =
+    cmd.subcommands.iter().partition(|sub| sub.hidden);
+  let hidden_subcmds = hidden_subcmds
+    .iter()
+    .map(|sub| &sub.name)
+    .chain(cmd.subcommands.iter().flat_map(|sub| &sub.aliases))
+    .collect::<Vec<_>>();
+  let visible_subcmds = visible_subcmds
+    .iter()
+    .map(|sub| sub.name.as_str())
+    .collect::<Vec<_>>()
+    .join(" ");
+  let subcmds_action = if hidden_subcmds.is_empty() {
+    visible_subcmds
+  } else {
+    out.writeln(format!("local -a subcmds=({visible_subcmds})"));
+    for name in hidden_subcmds {
+      out.writeln(format!(
+        r#"[[ -n $PREFIX && {} == "$PREFIX"* ]] && subcmds+=({name})"#,
+        util::quote_bash(name)
+      ));
+    }
+    "$subcmds".to_owned()
+  };
+
   if cmd.subcommands.is_empty() {
     out.write("_arguments");
   } else {
@@ -64,38 +127,43 @@ fn generate_fn(cmd: &CommandInfo, out: &mut Output, fn_name: &str) {
   }
 
   out.indent();
-  for flag in &cmd.flags {
-    let desc = if let Some(desc) = &flag.desc {
-      desc.replace('[', "\\[").replace(']', "\\]")
-    } else {
-      String::new()
-    };
-    for form in &flag.forms {
-      let text = util::quote_bash(format!("{form}[{desc}]"));
-      out.writeln(" \\");
-      out.write(text);
-    }
+  if has_persistent {
+    out.writeln(" \\");
+    out.write(r#""${persistent[@]}""#);
+  }
+  if !hidden_specs.is_empty() {
+    out.writeln(" \\");
+    out.write(r#""${hidden[@]}""#);
+  }
+  for spec in specs {
+    out.writeln(" \\");
+    out.write(spec);
   }
 
   if cmd.subcommands.is_empty() {
+    // Positional arguments are only completed for commands without
+    // subcommands, since the first one would be taken up by the subcommand
+    for arg in &cmd.args {
+      out.writeln(" \\");
+      out.write(util::quote_bash(arg_spec(arg)));
+    }
     out.dedent();
     out.writeln("");
   } else {
-    let sub_cmds = cmd
-      .subcommands
-      .iter()
-      .map(|c| c.name.to_string())
-      .collect::<Vec<_>>()
-      .join(" ");
     out.writeln(" \\");
-    out.writeln(format!("': :({sub_cmds})' \\"));
+    out.writeln(format!("': :({subcmds_action})' \\"));
     out.writeln("'*::arg:->args'");
     out.dedent();
 
     out.writeln("case $line[1] in");
     out.indent();
     for sub_cmd in &cmd.subcommands {
-      out.writeln(format!("{}) {fn_name}_{};;", sub_cmd.name, sub_cmd.name));
+      let names = std::iter::once(&sub_cmd.name)
+        .chain(&sub_cmd.aliases)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("|");
+      out.writeln(format!("{names}) {fn_name}_{};;", sub_cmd.name));
     }
     out.dedent();
     out.writeln("esac");
@@ -104,7 +172,294 @@ fn generate_fn(cmd: &CommandInfo, out: &mut Output, fn_name: &str) {
   out.dedent();
   out.writeln("}");
 
+  if !own_persistent.is_empty() {
+    out.writeln("");
+    out.writeln(format!("function {fn_name}_persistent_flags {{"));
+    out.indent();
+    let (specs, hidden_specs) = flag_specs(cmd, own_persistent.into_iter());
+    if !specs.is_empty() {
+      out.writeln("persistent+=(");
+      out.indent();
+      for spec in specs {
+        out.writeln(spec);
+      }
+      out.dedent();
+      out.writeln(")");
+    }
+    if !hidden_specs.is_empty() {
+      write_hidden(out, "persistent", &hidden_specs);
+    }
+    out.dedent();
+    out.writeln("}");
+  }
+
   for sub_cmd in &cmd.subcommands {
-    generate_fn(sub_cmd, out, &format!("{fn_name}_{}", sub_cmd.name));
+    generate_fn(
+      sub_cmd,
+      out,
+      &format!("{fn_name}_{}", sub_cmd.name),
+      has_persistent,
+    );
+  }
+}
+
+/// Add the specs for hidden forms to an array only if the user's typed the
+/// start of that form, not just dashes
+fn write_hidden(out: &mut Output, array: &str, specs: &[(&str, String)]) {
+  for (form, spec) in specs {
+    out.writeln(format!(
+      r#"[[ -n ${{PREFIX//-}} && {} == "$PREFIX"* ]] && {array}+=({spec})"#,
+      util::quote_bash(form)
+    ));
+  }
+}
+
+/// Make the quoted `_arguments` specs for all forms of the given flags. The
+/// specs for hidden flags and aliases are returned separately, along with
+/// their forms.
+fn flag_specs<'a>(
+  cmd: &CommandInfo,
+  flags: impl Iterator<Item = &'a Flag>,
+) -> (Vec<String>, Vec<(&'a str, String)>) {
+  let mut specs = Vec::new();
+  let mut hidden_specs = Vec::new();
+  for flag in flags {
+    let desc = if let Some(desc) =
+      util::describe(flag.desc.as_deref(), flag.deprecated)
+    {
+      desc.replace('[', "\\[").replace(']', "\\]")
+    } else {
+      String::new()
+    };
+    // Flags that can't be used with this one go in an exclusion list, e.g.
+    // `(--yaml)--json`. Zsh already hides a form once it's been used, but
+    // single-use flags also need to hide their other forms.
+    let all_forms = flag.forms.iter().chain(&flag.aliases).collect::<Vec<_>>();
+    let own_forms = if flag.repeatable || all_forms.len() == 1 {
+      &[][..]
+    } else {
+      all_forms.as_slice()
+    };
+    let excluded = own_forms
+      .iter()
+      .copied()
+      .chain(
+        util::conflicting_flags(cmd, flag)
+          .iter()
+          .flat_map(|other| &other.forms),
+      )
+      .map(String::as_str)
+      .collect::<Vec<_>>();
+    let exclusions = if excluded.is_empty() {
+      String::new()
+    } else {
+      format!("({})", excluded.join(" "))
+    };
+    let repeat = if flag.repeatable { "*" } else { "" };
+    for form in all_forms {
+      let spec = util::quote_bash(format!(
+        "{exclusions}{repeat}{}",
+        flag_spec(flag, form, &desc)
+      ));
+      if flag.hidden || flag.aliases.contains(form) {
+        hidden_specs.push((form.as_str(), spec));
+      } else {
+        specs.push(spec);
+      }
+    }
+  }
+  (specs, hidden_specs)
+}
+
+/// Make the spec `_arguments` uses for one form of a flag, e.g.
+/// `--output=[Where to write]:FILE:_files`
+fn flag_spec(flag: &Flag, form: &str, desc: &str) -> String {
+  let count = flag.value_count();
+  if count == ValueCount::Zero {
+    return format!("{form}[{desc}]");
+  }
+
+  let long = form.len() > 2;
+  let sep = match (flag.arg_style(), long) {
+    (ArgStyle::Any, true) => "=",
+    (ArgStyle::Any, false) => "+",
+    (ArgStyle::Attached, true) => "=-",
+    (ArgStyle::Attached, false) => "-",
+    (ArgStyle::Separate, _) => "",
+  };
+  let name = flag
+    .arg
+    .as_ref()
+    .and_then(|arg| arg.name.as_deref())
+    .unwrap_or("value")
+    .replace(':', "\\:");
+  let action = flag.typ.as_ref().map_or_else(|| " ".to_owned(), action);
+  let value = format!(":{name}:{action}");
+  let values = match count {
+    ValueCount::Zero => unreachable!(),
+    ValueCount::One => value,
+    ValueCount::Optional => format!(":{value}"),
+    ValueCount::Exactly(n) => value.repeat(n),
+    ValueCount::Variadic => format!(":*-*{value}"),
+  };
+  format!("{form}{sep}[{desc}]{values}")
+}
+
+/// Make the spec `_arguments` uses for a positional argument, e.g.
+/// `:FILE:_files` or `*:file to read:_files` for a variadic argument
+fn arg_spec(arg: &Arg) -> String {
+  let prefix = if arg.variadic {
+    "*"
+  } else if arg.optional {
+    ":"
+  } else {
+    ""
+  };
+  let message = arg
+    .desc
+    .as_deref()
+    .or(arg.name.as_deref())
+    .unwrap_or("argument")
+    .replace(':', "\\:");
+  let action = arg.typ.as_ref().map_or_else(|| " ".to_owned(), action);
+  format!("{prefix}:{message}:{action}")
+}
+
+/// An action that completes nothing, only showing what kind of value is
+/// expected
+fn message(hint: &str) -> String {
+  format!("_message {}", util::quote_bash(hint))
+}
+
+/// Turn a type into an action for `_arguments`. Types that can't be
+/// completed here get a single space as their action, so that only the value's
+/// name is shown.
+fn action(typ: &ArgType) -> String {
+  match typ {
+    ArgType::Path | ArgType::Unknown => "_files".to_owned(),
+    ArgType::Dir => "_files -/".to_owned(),
+    ArgType::Glob { globs, dirs } => {
+      let glob = if let [glob] = globs.as_slice() {
+        glob.to_owned()
+      } else {
+        format!("({})", globs.join("|"))
+      };
+      // _files also offers directories, _path_files only matching files
+      let func = if *dirs { "_files" } else { "_path_files" };
+      format!("{func} -g {}", util::quote_bash(glob))
+    }
+    ArgType::CommandName => "_command_names -e".to_owned(),
+    ArgType::User => "_users".to_owned(),
+    ArgType::Group => "_groups".to_owned(),
+    ArgType::Host => "_hosts".to_owned(),
+    ArgType::Pid => "_pids".to_owned(),
+    ArgType::Signal => "_signals".to_owned(),
+    ArgType::EnvVar => "_parameters -g '*export*'".to_owned(),
+    ArgType::NetInterface => "_net_interfaces".to_owned(),
+    ArgType::Port => "_ports".to_owned(),
+    ArgType::Strings(strs) => format!(
+      "({})",
+      strs
+        .iter()
+        .map(|(s, _)| {
+          s.chars().fold(String::new(), |mut s, c| {
+            if matches!(c, '\\' | '(' | ')' | ' ' | ':') {
+              s.push('\\');
+            }
+            s.push(c);
+            s
+          })
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+    ),
+    // Nothing to complete, but the kind of value expected can be shown
+    ArgType::Int { min, max } => message(&match (min, max) {
+      (Some(min), Some(max)) => format!("integer from {min} to {max}"),
+      (Some(min), None) => format!("integer (at least {min})"),
+      (None, Some(max)) => format!("integer (at most {max})"),
+      (None, None) => "integer".to_owned(),
+    }),
+    ArgType::Float => message("number"),
+    ArgType::Duration => message("duration"),
+    ArgType::Url => message("URL"),
+    ArgType::List { typ, sep } => {
+      let sep = util::quote_bash(sep);
+      match typ.as_ref() {
+        // _values also leaves out the values that were already given
+        ArgType::Strings(strs) => {
+          let specs = strs.iter().map(|(value, desc)| {
+            let value = value.chars().fold(String::new(), |mut s, c| {
+              if matches!(c, '\\' | '[' | ']' | ':') {
+                s.push('\\');
+              }
+              s.push(c);
+              s
+            });
+            let desc = desc.as_ref().map_or_else(String::new, |desc| {
+              format!("[{}]", desc.replace(['[', ']'], ""))
+            });
+            util::quote_bash(format!("{value}{desc}"))
+          });
+          format!(
+            "_values -s {sep} value {}",
+            specs.collect::<Vec<_>>().join(" ")
+          )
+        }
+        typ => {
+          let action = action(typ);
+          if action.starts_with('_') {
+            format!("_sequence -s {sep} {action}")
+          } else {
+            action
+          }
+        }
+      }
+    }
+    ArgType::Run { cmd, sep: None } => {
+      format!(r#"{{compadd -- ${{(f)"$({cmd})"}}}}"#)
+    }
+    // _describe wants the value and description separated by a colon
+    ArgType::Run {
+      cmd,
+      sep: Some(sep),
+    } => format!(
+      r#"{{local -a lines=(${{(f)"$({cmd})"}}); lines=(${{lines//:/\\:}}); lines=(${{lines/{}/:}}); _describe value lines}}"#,
+      util::quote_bash(sep.replace(':', "\\:"))
+    ),
+    ArgType::Any(types) => {
+      // Each alternative needs its own tag
+      let mut tags: Vec<String> = Vec::new();
+      let mut specs = Vec::new();
+      for typ in types {
+        let mut tag = tag(typ).to_owned();
+        if tags.contains(&tag) {
+          tag = format!("{tag}{}", tags.len() + 1);
+        }
+        specs.push(util::quote_bash(format!("{tag}:value:{}", action(typ))));
+        tags.push(tag);
+      }
+      format!("_alternative {}", specs.join(" "))
+    }
+    ArgType::Text => " ".to_owned(),
+  }
+}
+
+/// The tag to give the completions for a type when it's one of several
+/// alternatives
+fn tag(typ: &ArgType) -> &'static str {
+  match typ {
+    ArgType::Path | ArgType::Unknown | ArgType::Glob { .. } => "files",
+    ArgType::Dir => "directories",
+    ArgType::CommandName => "commands",
+    ArgType::User => "users",
+    ArgType::Group => "groups",
+    ArgType::Host => "hosts",
+    ArgType::Pid => "processes",
+    ArgType::Signal => "signals",
+    ArgType::EnvVar => "parameters",
+    ArgType::NetInterface => "interfaces",
+    ArgType::Port => "ports",
+    _ => "values",
   }
 }
@@ -6,30 +6,58 @@
 //! deserializes a KDL or JSON file to get command information. Both produce
 //! [`CommandInfo`]s that can then be used to generate shell completions using
 //! the [`gen`] module.
+//!
+//! [`CommandInfo`]s can also be combined using [`CommandInfo::merge`] and
+//! compared using [`CommandInfo::diff`].
 
+pub mod diff;
 pub mod gen;
+pub mod merge;
 pub mod parse_deser;
 pub mod parse_man;
+#[cfg(test)]
+mod test_util;
+
+use std::{fmt, str::FromStr};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Flags parsed from a command, as well as its parsed subcommands
-#[derive(Debug, Deserialize, Eq, Serialize, PartialEq)]
+#[derive(
+  Clone, Debug, Default, Deserialize, Eq, JsonSchema, Serialize, PartialEq,
+)]
 pub struct CommandInfo {
   pub name: String,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub desc: Option<String>,
-  #[serde(skip_serializing_if = "Vec::is_empty")]
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub flags: Vec<Flag>,
-  /// The types of the arguments to this command
+  /// The positional arguments to this command, in order
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
-  pub args: Vec<ArgType>,
+  pub args: Vec<Arg>,
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub subcommands: Vec<CommandInfo>,
+  /// Groups of flags that can't be used together, e.g. `--json` and `--yaml`.
+  /// Each flag is referred to by one of its forms.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub exclusive: Vec<Vec<String>>,
+  /// Other names the command can be called by, e.g. `rm` for `remove`. These
+  /// are only completed once they're being typed.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub aliases: Vec<String>,
+  /// Whether the command should only be completed once it's being typed
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub hidden: bool,
+  /// Whether the command is deprecated. This is shown in its description.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub deprecated: bool,
 }
 
 /// A parsed flag
-#[derive(Debug, Deserialize, Eq, Serialize, PartialEq)]
+#[derive(
+  Clone, Debug, Default, Deserialize, Eq, JsonSchema, Serialize, PartialEq,
+)]
 pub struct Flag {
   /// The different short and long forms of a flag
   pub forms: Vec<String>,
@@ -38,10 +66,244 @@ pub struct Flag {
   pub desc: Option<String>,
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub typ: Option<ArgType>,
+  /// The value(s) the flag takes. If not given, the flag is assumed to take a
+  /// single value if it has a type and no value otherwise (see
+  /// [`Flag::value_count`]).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub arg: Option<FlagArg>,
+  /// Forms of other flags that have to be given along with this one
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub requires: Vec<String>,
+  /// Forms of other flags that can't be given along with this one
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub conflicts: Vec<String>,
+  /// Whether the flag can be given more than once, e.g. `-v -v -v`.
+  /// Completions for other flags are hidden once they've been used.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub repeatable: bool,
+  /// Whether the flag is inherited by all of the command's subcommands (and
+  /// their subcommands), e.g. `--kubeconfig` for kubectl
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub persistent: bool,
+  /// Other forms of the flag, e.g. `--colour` for `--color`. Unlike `forms`,
+  /// these are only completed once they're being typed.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub aliases: Vec<String>,
+  /// Whether the flag should only be completed once it's being typed
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub hidden: bool,
+  /// Whether the flag is deprecated. This is shown in its description.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub deprecated: bool,
+}
+
+impl Flag {
+  /// How many values this flag takes, falling back to guessing from whether
+  /// or not it has a type
+  #[must_use]
+  pub fn value_count(&self) -> ValueCount {
+    match (&self.arg, &self.typ) {
+      (Some(arg), _) => arg.count,
+      (None, Some(_)) => ValueCount::One,
+      (None, None) => ValueCount::Zero,
+    }
+  }
+
+  /// How the flag's value(s) are given
+  #[must_use]
+  pub fn arg_style(&self) -> ArgStyle {
+    self.arg.as_ref().map_or(ArgStyle::Any, |arg| arg.style)
+  }
+}
+
+/// A positional argument to a command. Besides the full shape, it can be
+/// deserialized from just an [`ArgType`] (e.g. `"args": ["Path"]`), which is
+/// how arguments used to be written.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, Serialize, PartialEq)]
+#[serde(from = "ArgRepr")]
+pub struct Arg {
+  /// A placeholder name for the argument, e.g. `FILE`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub desc: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub typ: Option<ArgType>,
+  /// Whether the argument can be left out. Only arguments at the end should
+  /// be optional.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub optional: bool,
+  /// Whether the argument can be given more than once, e.g. `FILE...`. Only
+  /// the last argument should be variadic.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub variadic: bool,
+}
+
+/// An unnamed, required argument of the given type
+impl From<ArgType> for Arg {
+  fn from(typ: ArgType) -> Self {
+    Arg {
+      name: None,
+      desc: None,
+      typ: Some(typ),
+      optional: false,
+      variadic: false,
+    }
+  }
+}
+
+/// The shapes an [`Arg`] can be deserialized from
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ArgRepr {
+  Type(ArgType),
+  Full {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    desc: Option<String>,
+    #[serde(default)]
+    typ: Option<ArgType>,
+    #[serde(default)]
+    optional: bool,
+    #[serde(default)]
+    variadic: bool,
+  },
+}
+
+impl From<ArgRepr> for Arg {
+  fn from(repr: ArgRepr) -> Self {
+    match repr {
+      ArgRepr::Type(typ) => typ.into(),
+      ArgRepr::Full {
+        name,
+        desc,
+        typ,
+        optional,
+        variadic,
+      } => Arg {
+        name,
+        desc,
+        typ,
+        optional,
+        variadic,
+      },
+    }
+  }
+}
+
+/// The value(s) a flag takes, e.g. `FILE` in `--output FILE`
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, Serialize, PartialEq)]
+pub struct FlagArg {
+  /// How many values the flag takes
+  pub count: ValueCount,
+  /// A placeholder name for the value, e.g. `FILE`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  /// How the value is given
+  #[serde(default, skip_serializing_if = "ArgStyle::is_any")]
+  pub style: ArgStyle,
+}
+
+/// How many values a flag takes
+#[derive(
+  Clone, Copy, Debug, Deserialize, Eq, JsonSchema, Serialize, PartialEq,
+)]
+pub enum ValueCount {
+  /// The flag doesn't take a value, e.g. `--verbose`
+  Zero,
+  /// The flag takes exactly one value, e.g. `--output FILE`
+  One,
+  /// The value can be left out, e.g. `--color[=WHEN]`
+  Optional,
+  /// The flag takes this many values, e.g. `--point X Y`
+  Exactly(usize),
+  /// The flag takes one or more values, e.g. `--exclude PAT...`
+  Variadic,
+}
+
+/// How a flag's value is given
+#[derive(
+  Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, Serialize, PartialEq,
+)]
+pub enum ArgStyle {
+  /// Either in the same word as the flag or as the next word
+  #[default]
+  Any,
+  /// Only in the same word as the flag, i.e. `--flag=value` for long forms
+  /// and `-fvalue` for short forms
+  Attached,
+  /// Only as the next word, e.g. `--flag value`
+  Separate,
+}
+
+impl ArgStyle {
+  fn is_any(&self) -> bool {
+    *self == ArgStyle::Any
+  }
+}
+
+/// Parses the names used in KDL/TOML/YAML files: `zero`, `one`, `optional`,
+/// `variadic`, or a number
+impl FromStr for ValueCount {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "zero" => Ok(ValueCount::Zero),
+      "one" => Ok(ValueCount::One),
+      "optional" => Ok(ValueCount::Optional),
+      "variadic" => Ok(ValueCount::Variadic),
+      s => s.parse().map(ValueCount::Exactly).map_err(|_| {
+        format!(
+          "invalid value count {s}, expected zero, one, optional, variadic, or a number"
+        )
+      }),
+    }
+  }
+}
+
+impl fmt::Display for ValueCount {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValueCount::Zero => write!(f, "zero"),
+      ValueCount::One => write!(f, "one"),
+      ValueCount::Optional => write!(f, "optional"),
+      ValueCount::Exactly(n) => write!(f, "{n}"),
+      ValueCount::Variadic => write!(f, "variadic"),
+    }
+  }
+}
+
+/// Parses the names used in KDL/TOML/YAML files: `any`, `attached`, or
+/// `separate`
+impl FromStr for ArgStyle {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "any" => Ok(ArgStyle::Any),
+      "attached" => Ok(ArgStyle::Attached),
+      "separate" => Ok(ArgStyle::Separate),
+      s => Err(format!(
+        "invalid argument style {s}, expected any, attached, or separate"
+      )),
+    }
+  }
+}
+
+impl fmt::Display for ArgStyle {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ArgStyle::Any => write!(f, "any"),
+      ArgStyle::Attached => write!(f, "attached"),
+      ArgStyle::Separate => write!(f, "separate"),
+    }
+  }
 }
 
 /// How to complete an argument
-#[derive(Clone, Debug, Deserialize, Eq, Serialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, Serialize, PartialEq)]
 pub enum ArgType {
   /// Complete using either file or directory paths
   Path,
@@ -49,6 +311,16 @@ pub enum ArgType {
   /// Complete using directory paths
   Dir,
 
+  /// Complete using paths that match any of the given glob patterns, e.g.
+  /// `*.yaml`
+  Glob {
+    /// The patterns files have to match
+    globs: Vec<String>,
+    /// Whether directories are also completed, so that matching files inside
+    /// them can be found
+    dirs: bool,
+  },
+
   /// Complete by running a command
   Run {
     /// The command to run
@@ -65,6 +337,62 @@ pub enum ArgType {
   /// Complete with the name of a command
   CommandName,
 
+  /// Complete with the name of a user on this machine
+  User,
+
+  /// Complete with the name of a user group on this machine
+  Group,
+
+  /// Complete with a hostname, e.g. from `/etc/hosts` or the SSH config
+  Host,
+
+  /// Complete with the ID of a running process
+  Pid,
+
+  /// Complete with the name of a signal, e.g. `TERM`
+  Signal,
+
+  /// Complete with the name of an environment variable
+  EnvVar,
+
+  /// Complete with the name of a network interface, e.g. `eth0`
+  NetInterface,
+
+  /// Complete with a port number or service name, e.g. from `/etc/services`
+  Port,
+
+  /// An integer, optionally within a range. Nothing is completed
+  Int {
+    /// The smallest value allowed, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min: Option<i64>,
+    /// The largest value allowed, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max: Option<i64>,
+  },
+
+  /// A number that may have a fractional part. Nothing is completed
+  Float,
+
+  /// A length of time, e.g. `30s`. Nothing is completed
+  Duration,
+
+  /// A URL. Nothing is completed
+  Url,
+
+  /// Any string. Unlike [`ArgType::Unknown`], this means nothing should be
+  /// completed (rather than falling back to paths)
+  Text,
+
+  /// A list of values of some type joined by a separator, e.g. `a,b,c`.
+  /// Completion starts again after each separator
+  List {
+    /// The type of each value in the list
+    typ: Box<ArgType>,
+    /// What the values are separated by, e.g. `,`
+    sep: String,
+  },
+
   /// Any of the given types work
   Any(Vec<ArgType>),
 
@@ -1,16 +1,24 @@
-use std::{path::PathBuf, process::Command};
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  process::Command,
+  sync::atomic::{AtomicUsize, Ordering},
+};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use gen_completions::{
   gen::{self, OutputFormat},
-  parse_deser,
-  parse_man::{detect_subcommands, get_cmd_name, parse_from},
+  parse_deser::{self, overlay::Overlay, InputFormat},
+  parse_man::{cache::Cache, detect_subcommands, get_cmd_name, parse_from},
+  CommandInfo,
 };
 use log::{debug, error, info, warn};
-use miette::{miette, IntoDiagnostic, Result};
+use miette::{miette, IntoDiagnostic, Result, Severity};
+use rayon::prelude::*;
 use regex::Regex;
 
-/// Generate completions from either manpages or KDL/JSON/YAML files
+/// Generate completions from either manpages or KDL/JSON/TOML/YAML files
 #[derive(Debug, Parser)]
 #[command(version, about, long_about)]
 struct Cli {
@@ -22,12 +30,15 @@ struct Cli {
 enum Commands {
   /// Generate completions from manpages
   Man {
-    /// Shell(s) to generate completions for
-    shell: OutputFormat,
+    /// Shell(s) to generate completions for, e.g. `zsh` or `zsh,bash,nu`.
+    /// Possible values are zsh, bash, nu, kdl, json, and carapace.
+    #[arg(value_name = "SHELL,...", value_parser = shells_parser)]
+    shell: Shells,
 
-    /// Directory to output completions to
-    #[arg(value_delimiter = ',', value_name = "PATH")]
-    out: PathBuf,
+    /// Directory to output completions to. If multiple shells are given,
+    /// completions for each shell go in their own subdirectory.
+    #[arg(value_name = "PATH")]
+    out: Option<PathBuf>,
 
     /// Directories to search for man pages in, e.g.
     /// `--dirs=/usr/share/man/man1,/usr/share/man/man6`
@@ -56,18 +67,197 @@ enum Commands {
     /// `git-commit=git commit,foobar=foo bar`
     #[arg(long, value_name = "man-page=sub cmd,...", value_parser=subcmd_map_parser, value_delimiter = ',')]
     subcmds: Vec<(String, Vec<String>)>,
+
+    /// Number of man pages to parse in parallel. Defaults to the number of
+    /// CPUs.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Don't use the cache of parsed man pages. By default, parsed man pages
+    /// are cached in `$XDG_CACHE_HOME/gen-completions`, and commands whose
+    /// man pages didn't change aren't parsed again.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Directory with KDL/JSON overlays to patch the parsed commands with,
+    /// e.g. to add types, remove bogus flags, or rename subcommands. Each
+    /// overlay is applied to the command with the same name.
+    #[arg(long, value_name = "DIR")]
+    overlay: Option<PathBuf>,
+
+    #[command(flatten)]
+    gen_args: GenArgs,
   },
   /// Generate completions from a file
   For {
-    /// Shell(s) to generate completions for
-    shell: OutputFormat,
+    /// Shell(s) to generate completions for, e.g. `zsh` or `zsh,bash,nu`.
+    /// Possible values are zsh, bash, nu, kdl, json, and carapace.
+    #[arg(value_name = "SHELL,...", value_parser = shells_parser)]
+    shell: Shells,
 
-    /// File to generate completions from
+    /// File to generate completions from, or `-` to read from stdin. Can also
+    /// be a directory, in which case completions are generated for every
+    /// file in it.
     conf: PathBuf,
 
-    /// Folder to generate completions to. Outputted to stdout if not given.
+    /// Folder to generate completions to. If multiple shells are given,
+    /// completions for each shell go in their own subdirectory. Outputted to
+    /// stdout if not given and there's only one command.
     out: Option<PathBuf>,
+
+    /// Format of the input file. By default, this is figured out from the
+    /// file's extension, or from its contents if there's no (known) extension.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    input_format: Option<InputFormat>,
+
+    #[command(flatten)]
+    gen_args: GenArgs,
+  },
+  /// Report the flags and subcommands that were added, removed, or changed
+  /// between two versions of a command. Fails if anything was removed.
+  Diff {
+    /// The old version, either a config file or `man:<command>` to parse
+    /// the command's man pages
+    old: String,
+
+    /// The new version, either a config file or `man:<command>` to parse
+    /// the command's man pages
+    new: String,
+
+    /// Whether to print a human-readable or JSON report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
   },
+  /// Check a config file for problems, e.g. flags without dashes or `run`
+  /// types that run missing commands. Fails if any errors were found.
+  Lint {
+    /// File to check
+    file: PathBuf,
+
+    /// Fail on warnings too, not just errors
+    #[arg(long)]
+    deny_warnings: bool,
+  },
+  /// Print the JSON Schema for KDL/JSON config files
+  Schema,
+}
+
+/// How to print reports
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReportFormat {
+  Text,
+  Json,
+}
+
+/// The shells (or other formats) to generate completions for
+#[derive(Clone, Debug)]
+struct Shells(Vec<OutputFormat>);
+
+/// Options for generating completions, shared by `man` and `for`
+#[derive(Debug, Args)]
+struct GenArgs {
+  /// Maximum length of descriptions, either for all formats (e.g. `60`) or
+  /// for specific formats (e.g. `zsh=60,nu=100`). Longer descriptions are
+  /// shortened, and 0 means descriptions aren't shortened at all.
+  #[arg(long, value_name = "[FORMAT=]LEN,...", value_parser = desc_len_parser, value_delimiter = ',')]
+  max_desc_len: Vec<(Option<OutputFormat>, usize)>,
+
+  /// Directory to output Zsh completions to, instead of `<PATH>/zsh`
+  #[arg(long, value_name = "PATH")]
+  out_zsh: Option<PathBuf>,
+
+  /// Directory to output Bash completions to, instead of `<PATH>/bash`
+  #[arg(long, value_name = "PATH")]
+  out_bash: Option<PathBuf>,
+
+  /// Directory to output Nushell completions to, instead of `<PATH>/nu`
+  #[arg(long, value_name = "PATH")]
+  out_nu: Option<PathBuf>,
+
+  /// Directory to output KDL to, instead of `<PATH>/kdl`
+  #[arg(long, value_name = "PATH")]
+  out_kdl: Option<PathBuf>,
+
+  /// Directory to output JSON to, instead of `<PATH>/json`
+  #[arg(long, value_name = "PATH")]
+  out_json: Option<PathBuf>,
+
+  /// Directory to output Carapace specs to, instead of `<PATH>/carapace`
+  #[arg(long, value_name = "PATH")]
+  out_carapace: Option<PathBuf>,
+}
+
+impl GenArgs {
+  /// Find the maximum description length for a format. Lengths given for
+  /// that format specifically take precedence over lengths given for all
+  /// formats.
+  fn max_desc_len(&self, format: OutputFormat) -> usize {
+    let lens = &self.max_desc_len;
+    lens
+      .iter()
+      .rev()
+      .find(|(f, _)| *f == Some(format))
+      .or_else(|| lens.iter().rev().find(|(f, _)| f.is_none()))
+      .map_or(gen::MAX_DESC_LEN, |(_, len)| *len)
+  }
+
+  /// The directory explicitly given for a format using `--out-<format>`
+  fn explicit_out(&self, format: OutputFormat) -> Option<&PathBuf> {
+    match format {
+      OutputFormat::Zsh => self.out_zsh.as_ref(),
+      OutputFormat::Bash => self.out_bash.as_ref(),
+      OutputFormat::Nu => self.out_nu.as_ref(),
+      OutputFormat::Kdl => self.out_kdl.as_ref(),
+      OutputFormat::Json => self.out_json.as_ref(),
+      OutputFormat::Carapace => self.out_carapace.as_ref(),
+    }
+  }
+
+  /// Figure out which directory to output each format to, creating the
+  /// directories if necessary. If there's only one format, it goes directly
+  /// inside `out`, otherwise each format gets its own subdirectory.
+  fn out_dirs(
+    &self,
+    shells: &[OutputFormat],
+    out: Option<&Path>,
+  ) -> Result<Vec<(OutputFormat, PathBuf)>> {
+    let mut res: Vec<(OutputFormat, PathBuf)> = Vec::new();
+    for &shell in shells {
+      if res.iter().any(|(prev, _)| *prev == shell) {
+        continue;
+      }
+      let name = format_name(shell);
+      let dir = match (self.explicit_out(shell), out) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(out)) if shells.len() == 1 => out.to_path_buf(),
+        (None, Some(out)) => out.join(&name),
+        (None, None) => {
+          return Err(miette!(
+            "No output directory for {name}, give either an output directory or --out-{name}"
+          ))
+        }
+      };
+      fs::create_dir_all(&dir).into_diagnostic()?;
+      res.push((shell, dir));
+    }
+    Ok(res)
+  }
+}
+
+/// The name of a format, as given on the command line
+fn format_name(format: OutputFormat) -> String {
+  format
+    .to_possible_value()
+    .expect("Output formats shouldn't be skipped")
+    .get_name()
+    .to_owned()
+}
+
+fn shells_parser(s: &str) -> core::result::Result<Shells, String> {
+  s.split(',')
+    .map(|shell| OutputFormat::from_str(shell, true))
+    .collect::<core::result::Result<Vec<_>, _>>()
+    .map(Shells)
 }
 
 fn subcmd_map_parser(
@@ -82,6 +272,19 @@ fn subcmd_map_parser(
   Ok((String::from(page_name), as_subcmd))
 }
 
+fn desc_len_parser(
+  s: &str,
+) -> core::result::Result<(Option<OutputFormat>, usize), String> {
+  let (format, len) = match s.split_once('=') {
+    Some((format, len)) => (Some(OutputFormat::from_str(format, true)?), len),
+    None => (None, s),
+  };
+  let len = len
+    .parse()
+    .map_err(|_| format!("invalid description length '{len}'"))?;
+  Ok((format, len))
+}
+
 fn main() -> Result<()> {
   env_logger::init();
 
@@ -96,7 +299,33 @@ fn main() -> Result<()> {
       exclude_cmds,
       not_subcmds: _, // todo actually use this
       subcmds,
+      jobs,
+      no_cache,
+      overlay,
+      gen_args,
     } => {
+      if let Some(jobs) = jobs {
+        rayon::ThreadPoolBuilder::new()
+          .num_threads(jobs)
+          .build_global()
+          .into_diagnostic()?;
+      }
+      let out_dirs = gen_args.out_dirs(&shell.0, out.as_deref())?;
+      let cache = if no_cache {
+        None
+      } else {
+        let dir = Cache::default_dir();
+        if dir.is_none() {
+          warn!(
+            "Could not find a cache directory, not caching parsed man pages"
+          );
+        }
+        dir.map(|dir| Cache::new(dir, &subcmds))
+      };
+      let overlays = match overlay {
+        Some(dir) => load_overlays(&dir)?,
+        None => HashMap::new(),
+      };
       let search_dirs = match dirs {
         Some(dirs) => dirs.into_iter().collect::<Vec<_>>(),
         None => enumerate_dirs(get_manpath()?),
@@ -104,31 +333,178 @@ fn main() -> Result<()> {
 
       let manpages = enumerate_manpages(search_dirs, &cmds, &exclude_cmds);
 
-      let all_cmds = detect_subcommands(manpages, subcmds);
+      info!("Detecting subcommands in {} man pages", manpages.len());
+      let detected = cache
+        .as_ref()
+        .map(Cache::detected_subcmds)
+        .unwrap_or_default();
+      let all_cmds = detect_subcommands(manpages, subcmds, &detected);
+      let mut all_cmds = all_cmds.into_iter().collect::<Vec<_>>();
+      for name in overlays.keys() {
+        if !all_cmds.iter().any(|(cmd_name, _)| cmd_name == name) {
+          warn!("Overlay for {name} doesn't match any command, ignoring it");
+        }
+      }
+      // Sort so that the logs are in a predictable order
+      all_cmds.sort_by(|(a, _), (b, _)| a.cmp(b));
+
       let total = all_cmds.len();
-      for (i, (cmd_name, cmd_info)) in all_cmds.into_iter().enumerate() {
-        info!("Parsing {cmd_name} ({}/{})", i + 1, total);
+      let done = AtomicUsize::new(0);
+      let generated = AtomicUsize::new(0);
+      all_cmds
+        .into_par_iter()
+        .try_for_each(|(cmd_name, cmd_info)| {
+          let res = if let Some(cmd) = cache
+            .as_ref()
+            .and_then(|cache| cache.get(&cmd_name, &cmd_info))
+          {
+            debug!("Using cached info for {cmd_name}");
+            Some(cmd)
+          } else {
+            let stamps =
+              cache.as_ref().map(|_| Cache::stamps(&cmd_name, &cmd_info));
+            let (res, errors) = parse_from(&cmd_name, cmd_info);
+
+            for error in errors {
+              error!("{}", error);
+            }
+
+            if let (Some(cache), Some(stamps), Some(cmd)) =
+              (&cache, stamps, &res)
+            {
+              if let Err(e) =
+                stamps.and_then(|stamps| cache.insert(&cmd_name, stamps, cmd))
+              {
+                warn!("Could not cache {cmd_name}: {e}");
+              }
+            }
+            res
+          };
 
-        let (res, errors) = parse_from(&cmd_name, cmd_info);
+          if let Some(mut cmd_info) = res {
+            if let Some(overlay) = overlays.get(&cmd_name) {
+              debug!("Applying overlay to {cmd_name}");
+              overlay.apply(&mut cmd_info);
+            }
+            for (shell, out) in &out_dirs {
+              let written = gen::generate_to_file(
+                &cmd_info,
+                *shell,
+                gen_args.max_desc_len(*shell),
+                out,
+              )
+              .into_diagnostic()?;
+              if !written {
+                debug!(
+                  "Completions for {cmd_name} in {} unchanged",
+                  out.display()
+                );
+              }
+            }
+            generated.fetch_add(1, Ordering::Relaxed);
+          } else {
+            warn!("Could not parse man page for {cmd_name}");
+          }
 
-        for error in errors {
-          error!("{}", error);
+          let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+          info!("Processed {cmd_name} ({done}/{total})");
+          Ok::<_, miette::Report>(())
+        })?;
+
+      info!(
+        "Generated completions for {}/{total} commands",
+        generated.into_inner()
+      );
+    }
+    Commands::For {
+      shell,
+      conf,
+      out,
+      input_format,
+      gen_args,
+    } => {
+      let cmds = if conf.as_os_str() == "-" {
+        parse_deser::parse_stdin(input_format)?
+      } else if conf.is_dir() {
+        parse_deser::parse_dir(&conf)?
+      } else {
+        parse_deser::parse_all(&conf, input_format)?
+      };
+      for (i, cmd) in cmds.iter().enumerate() {
+        if cmds[..i].iter().any(|prev| prev.name == cmd.name) {
+          return Err(miette!("Multiple commands named {}", cmd.name));
         }
+      }
 
-        if let Some(cmd_info) = res {
-          info!("Generating completions for {cmd_name}");
-          gen::generate_to_file(&cmd_info, shell, &out).into_diagnostic()?;
-        } else {
-          warn!("Could not parse man page for {cmd_name}");
+      if let ([cmd], [shell], None, None) = (
+        cmds.as_slice(),
+        shell.0.as_slice(),
+        &out,
+        gen_args.explicit_out(shell.0[0]),
+      ) {
+        let desc_len = gen_args.max_desc_len(*shell);
+        println!("{}", gen::generate_to_str(cmd, *shell, desc_len));
+      } else {
+        for (shell, out) in gen_args.out_dirs(&shell.0, out.as_deref())? {
+          for cmd in &cmds {
+            gen::generate_to_file(
+              cmd,
+              shell,
+              gen_args.max_desc_len(shell),
+              &out,
+            )
+            .into_diagnostic()?;
+          }
         }
       }
     }
-    Commands::For { shell, conf, out } => {
-      let cmd = parse_deser::parse(conf)?;
-      if let Some(out) = out {
-        gen::generate_to_file(&cmd, shell, out).into_diagnostic()?;
-      } else {
-        println!("{}", gen::generate_to_str(&cmd, shell));
+    Commands::Diff { old, new, format } => {
+      let diff = load_cmd(&old)?.diff(&load_cmd(&new)?);
+      match format {
+        ReportFormat::Text => print!("{diff}"),
+        ReportFormat::Json => {
+          println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).into_diagnostic()?
+          );
+        }
+      }
+      if !diff.removed.is_empty() {
+        return Err(miette!(
+          "{} flags or subcommands were removed",
+          diff.removed.len()
+        ));
+      }
+    }
+    Commands::Schema => {
+      println!(
+        "{}",
+        serde_json::to_string_pretty(&parse_deser::schema::json_schema())
+          .into_diagnostic()?
+      );
+    }
+    Commands::Lint {
+      file,
+      deny_warnings,
+    } => {
+      let reports = parse_deser::lint(&file)?;
+      for report in &reports {
+        // Debug-formatting a Report renders it with the installed miette
+        // handler, as a graphical diagnostic with the file's source
+        eprintln!("{report:?}");
+      }
+      let failures = reports
+        .iter()
+        .filter(|report| {
+          deny_warnings
+            || !matches!(
+              report.severity(),
+              Some(Severity::Warning | Severity::Advice)
+            )
+        })
+        .count();
+      if failures > 0 {
+        return Err(miette!("Found {failures} problems in {}", file.display()));
       }
     }
   }
@@ -136,6 +512,59 @@ fn main() -> Result<()> {
   Ok(())
 }
 
+/// Load a command either from a config file or, given `man:<command>`, from
+/// the command's man pages
+fn load_cmd(source: &str) -> Result<CommandInfo> {
+  let Some(cmd_name) = source.strip_prefix("man:") else {
+    return Ok(parse_deser::parse(source)?);
+  };
+
+  // Include man pages for subcommands, e.g. `git-commit` for `git`
+  let pages_re = Regex::new(&format!("^{}(-|$)", regex::escape(cmd_name)))
+    .into_diagnostic()?;
+  let manpages =
+    enumerate_manpages(enumerate_dirs(get_manpath()?), &Some(pages_re), &None);
+  let cmd_info = detect_subcommands(manpages, Vec::new(), &HashMap::new())
+    .remove(cmd_name)
+    .ok_or_else(|| miette!("Could not find man page for {cmd_name}"))?;
+  let (res, errors) = parse_from(cmd_name, cmd_info);
+  for error in errors {
+    error!("{}", error);
+  }
+  res.ok_or_else(|| miette!("Could not parse man page for {cmd_name}"))
+}
+
+/// Load all the overlays in a directory, keyed by the names of the commands
+/// they're for
+fn load_overlays(dir: &Path) -> Result<HashMap<String, Overlay>> {
+  let mut overlays = HashMap::new();
+  let mut files = fs::read_dir(dir)
+    .into_diagnostic()?
+    .map(|entry| entry.map(|entry| entry.path()))
+    .collect::<std::io::Result<Vec<_>>>()
+    .into_diagnostic()?;
+  files.sort();
+  for file in files {
+    if !matches!(
+      file.extension().and_then(|ext| ext.to_str()),
+      Some("kdl" | "json")
+    ) {
+      debug!("Skipping non-overlay file {}", file.display());
+      continue;
+    }
+    let overlay = parse_deser::parse_overlay(&file)?;
+    if overlays.contains_key(&overlay.name) {
+      return Err(miette!(
+        "Multiple overlays for {}, found another in {}",
+        overlay.name,
+        file.display()
+      ));
+    }
+    overlays.insert(overlay.name.clone(), overlay);
+  }
+  Ok(overlays)
+}
+
 /// Find the search path for man by `manpath`, then `man --path`.
 fn get_manpath() -> Result<Vec<PathBuf>> {
   if let Ok(manpath) = std::env::var("MANPATH") {
@@ -205,12 +634,10 @@ fn enumerate_manpages(
       for manpage in manpages.flatten() {
         let path = manpage.path();
         let cmd_name = get_cmd_name(&path);
-        let &include = &include_re
-          .as_ref()
-          .map_or(true, |re| re.is_match(&cmd_name));
-        let &exclude = &exclude_re
-          .as_ref()
-          .map_or(false, |re| re.is_match(&cmd_name));
+        let include =
+          include_re.as_ref().is_none_or(|re| re.is_match(&cmd_name));
+        let exclude =
+          exclude_re.as_ref().is_some_and(|re| re.is_match(&cmd_name));
         if include && exclude && include_re.is_some() {
           warn!("Command {} was both included and excluded explicitly, will exclude", cmd_name);
         }
@@ -0,0 +1,270 @@
+//! For combining two [`CommandInfo`]s, e.g. one parsed from a man page and one
+//! written by hand
+
+use crate::{Arg, ArgType, CommandInfo, Flag};
+
+/// What to do when both commands have something different in the same place
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MergeStrategy {
+  /// Keep the left (`self`) side's descriptions, types, and arguments, only
+  /// using the right side's if the left side doesn't have any
+  PreferLeft,
+  /// Keep the right (`other`) side's descriptions, types, and arguments,
+  /// only using the left side's if the right side doesn't have any
+  PreferRight,
+  /// Like [`MergeStrategy::PreferLeft`], but conflicting types are combined
+  /// with [`ArgType::Any`] so that both sides' completions are offered
+  Union,
+}
+
+impl CommandInfo {
+  /// Merge two commands together. Flags are matched if they share any forms,
+  /// and subcommands are matched by name. Flags and subcommands found on
+  /// only one side are kept, and matched flags get the forms from both sides.
+  ///
+  /// The merged command has the left side's name.
+  #[must_use]
+  pub fn merge(&self, other: &CommandInfo, strategy: MergeStrategy) -> Self {
+    let mut flags: Vec<Flag> = self.flags.clone();
+    for right in &other.flags {
+      match find_flag(&mut flags, &right.forms) {
+        Some(left) => *left = merge_flags(left, right, strategy),
+        None => flags.push(right.clone()),
+      }
+    }
+
+    let mut subcommands = self.subcommands.clone();
+    for right in &other.subcommands {
+      let left = subcommands.iter_mut().find(|left| left.name == right.name);
+      match left {
+        Some(left) => *left = left.merge(right, strategy),
+        None => subcommands.push(right.clone()),
+      }
+    }
+
+    CommandInfo {
+      name: self.name.clone(),
+      desc: pick(&self.desc, &other.desc, strategy),
+      flags,
+      args: merge_args(&self.args, &other.args, strategy),
+      subcommands,
+      exclusive: union(&self.exclusive, &other.exclusive),
+      aliases: union(&self.aliases, &other.aliases),
+      hidden: self.hidden || other.hidden,
+      deprecated: self.deprecated || other.deprecated,
+    }
+  }
+}
+
+/// Find the flag sharing any of the given forms
+pub(crate) fn find_flag<'a>(
+  flags: &'a mut [Flag],
+  forms: &[String],
+) -> Option<&'a mut Flag> {
+  flags
+    .iter_mut()
+    .find(|flag| flag.forms.iter().any(|form| forms.contains(form)))
+}
+
+fn merge_flags(left: &Flag, right: &Flag, strategy: MergeStrategy) -> Flag {
+  let typ = match (&left.typ, &right.typ, strategy) {
+    (Some(l), Some(r), MergeStrategy::Union) => Some(union_types(l, r)),
+    _ => pick(&left.typ, &right.typ, strategy),
+  };
+
+  Flag {
+    forms: union(&left.forms, &right.forms),
+    desc: pick(&left.desc, &right.desc, strategy),
+    typ,
+    arg: pick(&left.arg, &right.arg, strategy),
+    requires: union(&left.requires, &right.requires),
+    conflicts: union(&left.conflicts, &right.conflicts),
+    repeatable: left.repeatable || right.repeatable,
+    persistent: left.persistent || right.persistent,
+    aliases: union(&left.aliases, &right.aliases),
+    hidden: left.hidden || right.hidden,
+    deprecated: left.deprecated || right.deprecated,
+  }
+}
+
+fn merge_args(
+  left: &[Arg],
+  right: &[Arg],
+  strategy: MergeStrategy,
+) -> Vec<Arg> {
+  match strategy {
+    MergeStrategy::PreferLeft if !left.is_empty() => left.to_vec(),
+    MergeStrategy::PreferRight if !right.is_empty() => right.to_vec(),
+    MergeStrategy::PreferLeft => right.to_vec(),
+    MergeStrategy::PreferRight => left.to_vec(),
+    // Combine the arguments at each position
+    MergeStrategy::Union => (0..left.len().max(right.len()))
+      .map(|i| match (left.get(i), right.get(i)) {
+        (Some(l), Some(r)) => Arg {
+          name: l.name.clone().or_else(|| r.name.clone()),
+          desc: l.desc.clone().or_else(|| r.desc.clone()),
+          typ: match (&l.typ, &r.typ) {
+            (Some(l), Some(r)) => Some(union_types(l, r)),
+            (typ, None) | (None, typ) => typ.clone(),
+          },
+          optional: l.optional || r.optional,
+          variadic: l.variadic || r.variadic,
+        },
+        (Some(arg), None) | (None, Some(arg)) => arg.clone(),
+        (None, None) => unreachable!("Index should be in bounds"),
+      })
+      .collect(),
+  }
+}
+
+/// Everything from the left side, then anything from the right side that
+/// isn't already there. Used for forms and relations between flags, since a
+/// relation that holds on one side should hold on the merged command too.
+pub(crate) fn union<T: Clone + PartialEq>(left: &[T], right: &[T]) -> Vec<T> {
+  let mut res = left.to_vec();
+  for item in right {
+    if !res.contains(item) {
+      res.push(item.clone());
+    }
+  }
+  res
+}
+
+/// Pick one side's value, falling back to the other side's
+fn pick<T: Clone>(
+  left: &Option<T>,
+  right: &Option<T>,
+  strategy: MergeStrategy,
+) -> Option<T> {
+  match strategy {
+    MergeStrategy::PreferLeft | MergeStrategy::Union => {
+      left.as_ref().or(right.as_ref()).cloned()
+    }
+    MergeStrategy::PreferRight => right.as_ref().or(left.as_ref()).cloned(),
+  }
+}
+
+/// Combine two types so that values from either one are completed
+fn union_types(left: &ArgType, right: &ArgType) -> ArgType {
+  if left == right {
+    return left.clone();
+  }
+
+  let mut types = Vec::new();
+  for typ in [left, right] {
+    let alts = match typ {
+      ArgType::Any(alts) => alts.as_slice(),
+      typ => std::slice::from_ref(typ),
+    };
+    for alt in alts {
+      if !types.contains(alt) {
+        types.push(alt.clone());
+      }
+    }
+  }
+  ArgType::Any(types)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::MergeStrategy;
+  use crate::{
+    test_util::{cmd, flag},
+    Arg, ArgType, CommandInfo, Flag,
+  };
+
+  fn sides() -> (CommandInfo, CommandInfo) {
+    let left = CommandInfo {
+      desc: Some("From the man page".to_owned()),
+      ..cmd(
+        "foo",
+        vec![
+          Flag {
+            typ: Some(ArgType::Path),
+            ..flag(&["-o"], Some("Output"))
+          },
+          flag(&["-q"], Some("Quiet")),
+        ],
+        vec![cmd("bar", vec![], vec![])],
+      )
+    };
+    let right = CommandInfo {
+      desc: Some("Written by hand".to_owned()),
+      ..cmd(
+        "foo",
+        vec![
+          Flag {
+            typ: Some(ArgType::Dir),
+            ..flag(&["--output", "-o"], None)
+          },
+          flag(&["-v"], Some("Verbose")),
+        ],
+        vec![bar(), cmd("baz", vec![], vec![])],
+      )
+    };
+    (left, right)
+  }
+
+  fn bar() -> CommandInfo {
+    CommandInfo {
+      desc: Some("Do bar".to_owned()),
+      ..cmd("bar", vec![], vec![])
+    }
+  }
+
+  #[test]
+  fn test_prefer_left() {
+    let (left, right) = sides();
+    let expected = CommandInfo {
+      desc: Some("From the man page".to_owned()),
+      ..cmd(
+        "foo",
+        vec![
+          Flag {
+            typ: Some(ArgType::Path),
+            ..flag(&["-o", "--output"], Some("Output"))
+          },
+          flag(&["-q"], Some("Quiet")),
+          flag(&["-v"], Some("Verbose")),
+        ],
+        vec![bar(), cmd("baz", vec![], vec![])],
+      )
+    };
+    assert_eq!(expected, left.merge(&right, MergeStrategy::PreferLeft));
+  }
+
+  #[test]
+  fn test_prefer_right() {
+    let (left, right) = sides();
+    let merged = left.merge(&right, MergeStrategy::PreferRight);
+    assert_eq!(Some("Written by hand".to_owned()), merged.desc);
+    assert_eq!(
+      Flag {
+        typ: Some(ArgType::Dir),
+        ..flag(&["-o", "--output"], Some("Output"))
+      },
+      merged.flags[0]
+    );
+  }
+
+  #[test]
+  fn test_union() {
+    let (mut left, mut right) = sides();
+    left.args = vec![ArgType::Path.into()];
+    right.args = vec![ArgType::CommandName.into(), ArgType::Dir.into()];
+    let merged = left.merge(&right, MergeStrategy::Union);
+    assert_eq!(
+      Some(ArgType::Any(vec![ArgType::Path, ArgType::Dir])),
+      merged.flags[0].typ
+    );
+    assert_eq!(
+      vec![
+        Arg::from(ArgType::Any(vec![ArgType::Path, ArgType::CommandName])),
+        Arg::from(ArgType::Dir)
+      ],
+      merged.args
+    );
+  }
+}
@@ -1,19 +1,23 @@
 use std::io;
 
-use miette::{Diagnostic, NamedSource};
+use miette::{Diagnostic, NamedSource, SourceSpan};
 use thiserror::Error;
 
-use super::kdl::KdlDeserError;
+use super::{kdl::KdlDeserError, schema::SchemaError};
 
 #[derive(Debug, Diagnostic, Error)]
 pub enum Error {
-  #[error("{file_path} has no extension")]
-  #[diagnostic(code(gen_completions::deser::no_ext), url(docsrs))]
-  NoExtension { file_path: String },
-
-  #[error("{file_path} has an unrecognizable extension")]
-  #[diagnostic(code(gen_completions::deser::unrecognizable_ext), url(docsrs))]
-  UnrecognizableExtension { file_path: String },
+  /// The file's extension wasn't recognized and its contents weren't valid
+  /// in any of the supported formats
+  #[error("couldn't tell what format {file_path} is in")]
+  #[diagnostic(
+    code(gen_completions::deser::unknown_format),
+    url(docsrs),
+    help(
+      "give it a .kdl, .json, .toml, or .yaml extension, or give the format explicitly"
+    )
+  )]
+  UnknownFormat { file_path: String },
 
   #[error("Error encountered while reading {file_path}")]
   #[diagnostic(code(gen_completions::deser::io_error), url(docsrs))]
@@ -40,7 +44,53 @@ pub enum DeserError {
   #[diagnostic()]
   Json(#[from] serde_json::Error),
 
+  #[error("invalid JSON: {error}")]
+  #[diagnostic(code(gen_completions::deser::json_syntax), url(docsrs))]
+  JsonSyntax {
+    error: serde_json::Error,
+    #[label("here")]
+    span: SourceSpan,
+  },
+
+  /// The JSON was valid but didn't match the schema for a [`CommandInfo`]
+  ///
+  /// [`CommandInfo`]: crate::CommandInfo
+  #[error("JSON didn't match the schema")]
+  #[diagnostic(
+    code(gen_completions::deser::schema),
+    url(docsrs),
+    help("see the schema with `gen-completions schema`")
+  )]
+  Schema {
+    #[related]
+    errors: Vec<SchemaError>,
+  },
+
   #[error(transparent)]
   #[diagnostic(transparent)]
   Kdl(#[from] KdlDeserError),
+
+  #[error("invalid TOML: {message}")]
+  #[diagnostic(code(gen_completions::deser::toml), url(docsrs))]
+  Toml {
+    message: String,
+    #[label("here")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("invalid YAML: {message}")]
+  #[diagnostic(code(gen_completions::deser::yaml), url(docsrs))]
+  Yaml {
+    message: String,
+    #[label("here")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("{what} can't be written in {format}")]
+  #[diagnostic(
+    code(gen_completions::deser::unsupported_format),
+    url(docsrs),
+    help("use KDL or JSON instead")
+  )]
+  UnsupportedFormat { what: String, format: String },
 }
@@ -1,12 +1,17 @@
 //! For deserializing from KDL, because the serde support is not great
 
-use std::collections::HashMap;
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+};
 
 use kdl::{KdlDocument, KdlNode};
 use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 
-use crate::{ArgType, CommandInfo, Flag};
+use super::overlay::{FlagPatch, Overlay};
+use crate::{Arg, ArgStyle, ArgType, CommandInfo, Flag, FlagArg, ValueCount};
 
 /// An error encountered when deserializing KDL specifically
 #[derive(Debug, Diagnostic, Error)]
@@ -31,6 +36,36 @@ pub enum KdlDeserError {
     source_code: String,
     error: ParseError,
   },
+
+  /// Something went wrong in a file that was included using `include`
+  #[error("Error encountered in included file {file_path}")]
+  #[diagnostic(forward(error))]
+  Included {
+    file_path: String,
+    /// The contents of the included file
+    text: String,
+    #[source]
+    error: Box<KdlDeserError>,
+  },
+}
+
+impl KdlDeserError {
+  /// If the error was in an included file, the path and contents of the
+  /// innermost included file where it happened, so the error can be shown
+  /// with the right source code
+  #[must_use]
+  pub fn included_file(&self) -> Option<(&str, &str)> {
+    match self {
+      KdlDeserError::Included {
+        file_path,
+        text,
+        error,
+      } => error
+        .included_file()
+        .or(Some((file_path.as_str(), text.as_str()))),
+      _ => None,
+    }
+  }
 }
 
 #[derive(Debug, Diagnostic, Error)]
@@ -121,17 +156,73 @@ pub enum ParseError {
 
 type ParseResult<T> = std::result::Result<T, ParseError>;
 
-/// Parse a string as KDL and convert it to a [`CommandInfo`]
+/// Parse a string as KDL and convert it to a [`CommandInfo`]. Included files
+/// are looked for relative to `dir`.
 ///
 /// # Errors
 ///
 /// Possible reasons for failure:
 /// - The document isn't valid KDL
-/// - The document doesn't have exactly one node
+/// - The document doesn't have exactly one command
 /// - The format of the document doesn't match the shape of a [`CommandInfo`]
+/// - An included file couldn't be read or has one of the problems above
 pub fn parse_from_str(
   text: &str,
+  dir: &Path,
 ) -> std::result::Result<CommandInfo, KdlDeserError> {
+  let mut cmds = parse_all_from_str(text, dir)?;
+  if cmds.len() > 1 {
+    Err(KdlDeserError::TooManyNodes(cmds.len()))
+  } else {
+    Ok(cmds.remove(0))
+  }
+}
+
+/// Parse a string as KDL and convert each of its top-level command nodes to a
+/// [`CommandInfo`]. Included files are looked for relative to `dir`.
+///
+/// # Errors
+///
+/// Same as [`parse_from_str`], except the document can have any number of
+/// commands (but at least one)
+pub fn parse_all_from_str(
+  text: &str,
+  dir: &Path,
+) -> std::result::Result<Vec<CommandInfo>, KdlDeserError> {
+  let doc: KdlDocument = text.parse()?;
+  let mut defs = Defs::default();
+  let cmd_nodes = load_defs(&doc, text, dir, &mut defs, &mut Vec::new())?;
+  if cmd_nodes.is_empty() {
+    return Err(KdlDeserError::EmptyFile);
+  }
+  cmd_nodes
+    .into_iter()
+    .map(|node| {
+      kdl_to_cmd_info(node, &defs).map_err(|error| KdlDeserError::ParseError {
+        source_code: text.to_string(),
+        error,
+      })
+    })
+    .collect()
+}
+
+/// Parse a string as KDL and convert it to an [`Overlay`]
+///
+/// # Errors
+///
+/// Same as [`parse_from_str`], except the document has to match the shape of
+/// an [`Overlay`]
+pub fn parse_overlay_from_str(
+  text: &str,
+) -> std::result::Result<Overlay, KdlDeserError> {
+  parse_single_node(text, kdl_to_overlay)
+}
+
+/// Parse a document that should have exactly one node and convert that node
+fn parse_single_node<T>(
+  text: &str,
+  convert: impl FnOnce(&KdlNode) -> ParseResult<T>,
+) -> std::result::Result<T, KdlDeserError> {
   let doc: KdlDocument = text.parse()?;
   let nodes = doc.nodes();
   if nodes.is_empty() {
@@ -139,37 +230,245 @@ pub fn parse_from_str(
   } else if nodes.len() > 1 {
     Err(KdlDeserError::TooManyNodes(nodes.len()))
   } else {
-    kdl_to_cmd_info(&nodes[0]).map_err(|error| KdlDeserError::ParseError {
+    convert(&nodes[0]).map_err(|error| KdlDeserError::ParseError {
       source_code: text.to_string(),
       error,
     })
   }
 }
 
+/// Named types and flag groups, which can be used in commands with `ref`
+#[derive(Default)]
+struct Defs {
+  types: HashMap<String, ArgType>,
+  flag_groups: HashMap<String, Vec<Flag>>,
+}
+
+/// Go through the top-level nodes of a document, handling `include`, `types`,
+/// and `flag-groups` nodes, and return the remaining nodes, which should be
+/// commands. Definitions can only use types and flag groups defined before
+/// them, but commands can use all of them.
+///
+/// `included` is the stack of files currently being included, to catch cycles
+fn load_defs<'a>(
+  doc: &'a KdlDocument,
+  text: &str,
+  dir: &Path,
+  defs: &mut Defs,
+  included: &mut Vec<PathBuf>,
+) -> std::result::Result<Vec<&'a KdlNode>, KdlDeserError> {
+  let parse_error = |error| KdlDeserError::ParseError {
+    source_code: text.to_string(),
+    error,
+  };
+
+  let mut cmd_nodes = Vec::new();
+  for node in doc.nodes() {
+    let directive = is_directive(node);
+    match node.name().value() {
+      "include" if directive => {
+        let path = dir.join(single_string(node).map_err(parse_error)?);
+        include(node, &path, defs, included).map_err(|error| match error {
+          IncludeError::Read(error) => parse_error(error),
+          IncludeError::Deser(error) => error,
+        })?;
+      }
+      "types" if directive => {
+        for type_node in node.children().map_or(&[][..], KdlDocument::nodes) {
+          let name = type_node.name().value().to_string();
+          if defs.types.contains_key(&name) {
+            return Err(parse_error(ParseError::Generic {
+              error: format!("type {name} was already defined"),
+              span: *type_node.name().span(),
+              label: "defined again here".to_owned(),
+              help: Some("rename one of the types".to_owned()),
+            }));
+          }
+          let typ = parse_type_node(type_node, defs).map_err(parse_error)?;
+          defs.types.insert(name, typ);
+        }
+      }
+      "flag-groups" if directive => {
+        for group_node in node.children().map_or(&[][..], KdlDocument::nodes) {
+          let name = group_node.name().value().to_string();
+          if defs.flag_groups.contains_key(&name) {
+            return Err(parse_error(ParseError::Generic {
+              error: format!("flag group {name} was already defined"),
+              span: *group_node.name().span(),
+              label: "defined again here".to_owned(),
+              help: Some("rename one of the flag groups".to_owned()),
+            }));
+          }
+          let flags = match group_node.children() {
+            Some(doc) => parse_flags(doc, defs).map_err(parse_error)?,
+            None => vec![],
+          };
+          defs.flag_groups.insert(name, flags);
+        }
+      }
+      _ => cmd_nodes.push(node),
+    }
+  }
+  Ok(cmd_nodes)
+}
+
+/// The children a command node can have
+const CMD_CHILDREN: &[&str] = &[
+  "flags",
+  "args",
+  "desc",
+  "subcommands",
+  "exclusive",
+  "aliases",
+];
+
+/// Whether a top-level node is an `include`, `types`, or `flag-groups`
+/// directive rather than a command that happens to have the same name.
+/// Commands never have arguments and only have children like `flags`, so the
+/// node's shape tells them apart.
+pub(super) fn is_directive(node: &KdlNode) -> bool {
+  match node.name().value() {
+    "include" => node.entries().iter().any(|entry| entry.name().is_none()),
+    "types" | "flag-groups" => node.children().is_some_and(|doc| {
+      doc
+        .nodes()
+        .iter()
+        .any(|child| !CMD_CHILDREN.contains(&child.name().value()))
+    }),
+    _ => false,
+  }
+}
+
+enum IncludeError {
+  /// The file couldn't be read, or there was a cycle. These are reported at
+  /// the `include` node
+  Read(ParseError),
+  /// Something was wrong inside the included file
+  Deser(KdlDeserError),
+}
+
+/// Load the definitions from an included file into `defs`
+fn include(
+  node: &KdlNode,
+  path: &Path,
+  defs: &mut Defs,
+  included: &mut Vec<PathBuf>,
+) -> std::result::Result<(), IncludeError> {
+  let read_error = |error: String| {
+    IncludeError::Read(ParseError::Generic {
+      error,
+      span: *node.span(),
+      label: "included here".to_owned(),
+      help: None,
+    })
+  };
+
+  let canonical = path.canonicalize().map_err(|e| {
+    read_error(format!("couldn't read {}: {e}", path.display()))
+  })?;
+  if included.contains(&canonical) {
+    return Err(read_error(format!(
+      "{} includes itself (maybe indirectly)",
+      path.display()
+    )));
+  }
+  let text = fs::read_to_string(path).map_err(|e| {
+    read_error(format!("couldn't read {}: {e}", path.display()))
+  })?;
+
+  included.push(canonical);
+  let res = text
+    .parse::<KdlDocument>()
+    .map_err(KdlDeserError::from)
+    .and_then(|doc| {
+      let dir = path.parent().unwrap_or(Path::new(""));
+      let cmd_nodes = load_defs(&doc, &text, dir, defs, included)?;
+      match cmd_nodes.first() {
+        Some(cmd_node) => Err(KdlDeserError::ParseError {
+          source_code: text.clone(),
+          error: ParseError::UnexpectedChild {
+            child_name: cmd_node.name().value().to_string(),
+            allowed: "one of [include, types, flag-groups]".to_owned(),
+            span: *cmd_node.name().span(),
+          },
+        }),
+        None => Ok(()),
+      }
+    });
+  included.pop();
+
+  res.map_err(|error| {
+    IncludeError::Deser(KdlDeserError::Included {
+      file_path: path.to_string_lossy().to_string(),
+      text,
+      error: Box::new(error),
+    })
+  })
+}
+
+/// Parse the children of a `flags` node. `ref "group"` nodes are replaced with
+/// the flags in that group.
+fn parse_flags(doc: &KdlDocument, defs: &Defs) -> ParseResult<Vec<Flag>> {
+  let mut flags = vec![];
+  let mut flag_spans = HashMap::new();
+  for flag_node in doc.nodes() {
+    if flag_node.name().value() == "ref" {
+      let name = single_string(flag_node)?;
+      let Some(group) = defs.flag_groups.get(&name) else {
+        return Err(ParseError::Generic {
+          error: format!("unknown flag group {name}"),
+          span: *flag_node.span(),
+          label: "not defined".to_owned(),
+          help: Some(
+            "define it inside a top-level flag-groups node".to_owned(),
+          ),
+        });
+      };
+      for flag in group {
+        for form in flag.forms.iter().chain(&flag.aliases) {
+          if let Some(prev_span) = flag_spans.get(form) {
+            return Err(ParseError::DuplicateFlag {
+              flag: form.clone(),
+              span: *flag_node.span(),
+              prev_span: *prev_span,
+            });
+          }
+          flag_spans.insert(form.clone(), *flag_node.span());
+        }
+        flags.push(flag.clone());
+      }
+    } else {
+      flags.push(parse_flag(flag_node, &mut flag_spans, defs)?);
+    }
+  }
+  Ok(flags)
+}
+
 /// Convert a KDL node representing a command to a [`CommandInfo`]
 ///
 /// Returns a list of all errors encountered along the way, if it failed
-fn kdl_to_cmd_info(node: &KdlNode) -> ParseResult<CommandInfo> {
+fn kdl_to_cmd_info(node: &KdlNode, defs: &Defs) -> ParseResult<CommandInfo> {
   let name = node.name().to_string();
   let mut flags = vec![];
   let mut args = vec![];
   let mut desc = None;
   let mut subcommands = vec![];
+  let mut exclusive = vec![];
+  let mut aliases = vec![];
+  let hidden = bool_prop(node, "hidden")?.unwrap_or(false);
+  let deprecated = bool_prop(node, "deprecated")?.unwrap_or(false);
 
   if let Some(doc) = node.children() {
-    let nodes = get_nodes(doc, &["flags", "args", "desc", "subcommands"])?;
+    let nodes = get_nodes(doc, CMD_CHILDREN)?;
 
     if let Some(flags_doc) = nodes.get("flags").and_then(|node| node.children())
     {
-      let mut flag_spans = HashMap::new();
-      for flag_node in flags_doc.nodes() {
-        flags.push(parse_flag(flag_node, &mut flag_spans)?);
-      }
+      flags = parse_flags(flags_doc, defs)?;
     }
 
     if let Some(arg_doc) = nodes.get("args").and_then(|node| node.children()) {
       for node in arg_doc.nodes() {
-        args.push(parse_type(node)?);
+        args.push(parse_arg(node, defs)?);
       }
     }
 
@@ -189,9 +488,24 @@ fn kdl_to_cmd_info(node: &KdlNode) -> ParseResult<CommandInfo> {
       nodes.get("subcommands").and_then(|node| node.children())
     {
       for subcmd_node in subcmds_doc.nodes() {
-        subcommands.push(kdl_to_cmd_info(subcmd_node)?);
+        subcommands.push(kdl_to_cmd_info(subcmd_node, defs)?);
       }
     }
+
+    // Each child is a group of flags, written like `"--json" "--yaml"`
+    if let Some(groups_doc) =
+      nodes.get("exclusive").and_then(|node| node.children())
+    {
+      for group_node in groups_doc.nodes() {
+        let mut group = vec![group_node.name().value().to_owned()];
+        group.extend(strings(group_node)?);
+        exclusive.push(group);
+      }
+    }
+
+    if let Some(aliases_node) = nodes.get("aliases") {
+      aliases = strings(aliases_node)?;
+    }
   }
 
   Ok(CommandInfo {
@@ -200,18 +514,190 @@ fn kdl_to_cmd_info(node: &KdlNode) -> ParseResult<CommandInfo> {
     flags,
     args,
     subcommands,
+    exclusive,
+    aliases,
+    hidden,
+    deprecated,
   })
 }
 
+/// Convert a KDL node representing a patch for a command to an [`Overlay`]
+fn kdl_to_overlay(node: &KdlNode) -> ParseResult<Overlay> {
+  // Overlays can't use named types or flag groups
+  let defs = Defs::default();
+  let mut overlay = Overlay {
+    name: node.name().value().to_string(),
+    hidden: bool_prop(node, "hidden")?,
+    deprecated: bool_prop(node, "deprecated")?,
+    ..Default::default()
+  };
+
+  let Some(doc) = node.children() else {
+    return Ok(overlay);
+  };
+  let nodes = get_nodes(
+    doc,
+    &[
+      "rename",
+      "desc",
+      "flags",
+      "remove-flags",
+      "args",
+      "subcommands",
+      "remove-subcommands",
+      "aliases",
+    ],
+  )?;
+
+  if let Some(rename_node) = nodes.get("rename") {
+    overlay.rename = Some(single_string(rename_node)?);
+  }
+
+  if let Some(desc_node) = nodes.get("desc") {
+    overlay.desc = Some(single_string(desc_node)?);
+  }
+
+  if let Some(flags_doc) = nodes.get("flags").and_then(|node| node.children()) {
+    let mut flag_spans = HashMap::new();
+    for flag_node in flags_doc.nodes() {
+      // `replace=true` isn't a flag, so take it out before parsing the rest
+      let replace = bool_prop(flag_node, "replace")?.unwrap_or(false);
+      let repeatable = bool_prop(flag_node, "repeatable")?;
+      let persistent = bool_prop(flag_node, "persistent")?;
+      let hidden = bool_prop(flag_node, "hidden")?;
+      let deprecated = bool_prop(flag_node, "deprecated")?;
+      // An empty child clears the list, while a missing one leaves it alone
+      let has_child = |name: &str| {
+        flag_node.children().is_some_and(|doc| {
+          doc.nodes().iter().any(|child| child.name().value() == name)
+        })
+      };
+      let requires_given = has_child("requires");
+      let conflicts_given = has_child("conflicts");
+      let aliases_given = has_child("aliases");
+      let mut flag_node = flag_node.clone();
+      flag_node.entries_mut().retain(|entry| {
+        entry.name().is_none_or(|name| name.value() != "replace")
+      });
+      let Flag {
+        forms,
+        desc,
+        typ,
+        arg,
+        requires,
+        conflicts,
+        aliases,
+        ..
+      } = parse_flag(&flag_node, &mut flag_spans, &defs)?;
+      overlay.flags.push(FlagPatch {
+        forms,
+        desc,
+        typ,
+        arg,
+        requires: requires_given.then_some(requires),
+        conflicts: conflicts_given.then_some(conflicts),
+        repeatable,
+        persistent,
+        aliases: aliases_given.then_some(aliases),
+        hidden,
+        deprecated,
+        replace,
+      });
+    }
+  }
+
+  if let Some(remove_node) = nodes.get("remove-flags") {
+    overlay.remove_flags = strings(remove_node)?;
+  }
+
+  if let Some(arg_doc) = nodes.get("args").and_then(|node| node.children()) {
+    overlay.args = Some(
+      arg_doc
+        .nodes()
+        .iter()
+        .map(|node| parse_arg(node, &defs))
+        .collect::<ParseResult<_>>()?,
+    );
+  }
+
+  if let Some(subcmds_doc) =
+    nodes.get("subcommands").and_then(|node| node.children())
+  {
+    for subcmd_node in subcmds_doc.nodes() {
+      overlay.subcommands.push(kdl_to_overlay(subcmd_node)?);
+    }
+  }
+
+  if let Some(remove_node) = nodes.get("remove-subcommands") {
+    overlay.remove_subcommands = strings(remove_node)?;
+  }
+
+  if let Some(aliases_node) = nodes.get("aliases") {
+    overlay.aliases = Some(strings(aliases_node)?);
+  }
+
+  Ok(overlay)
+}
+
+/// Get the strings in a node like `remove-flags "-a" "--all"`
+fn strings(node: &KdlNode) -> ParseResult<Vec<String>> {
+  node
+    .entries()
+    .iter()
+    .map(|entry| match (entry.name(), entry.value().as_string()) {
+      (None, Some(value)) => Ok(value.to_string()),
+      _ => Err(ParseError::Generic {
+        error: format!("expected strings, got {entry}"),
+        span: *entry.span(),
+        label: "should be a string".to_owned(),
+        help: Some("wrap it in quotes".to_owned()),
+      }),
+    })
+    .collect()
+}
+
+/// Get a boolean property like `replace=true`, if the node has it
+fn bool_prop(node: &KdlNode, name: &str) -> ParseResult<Option<bool>> {
+  node
+    .get(name)
+    .map(|entry| {
+      entry.value().as_bool().ok_or_else(|| ParseError::Generic {
+        error: format!("{name} should be a boolean"),
+        span: *entry.span(),
+        label: "expected true or false".to_owned(),
+        help: None,
+      })
+    })
+    .transpose()
+}
+
+/// Get the only string in a node like `desc "foo"`
+fn single_string(node: &KdlNode) -> ParseResult<String> {
+  match strings(node)?.as_slice() {
+    [value] => Ok(value.clone()),
+    _ => Err(ParseError::Generic {
+      error: format!("expected a single string for {}", node.name()),
+      span: *node.span(),
+      label: "should have exactly one entry".to_owned(),
+      help: None,
+    }),
+  }
+}
+
 /// `flag_spans` records the spans of all flags for the current command to find
 /// duplicates
 fn parse_flag(
   node: &KdlNode,
   flag_spans: &mut HashMap<String, SourceSpan>,
+  defs: &Defs,
 ) -> ParseResult<Flag> {
   let mut forms = vec![];
   let mut desc = None;
   let mut typ = None;
+  let mut arg = None;
+  let mut requires = vec![];
+  let mut conflicts = vec![];
+  let mut aliases = vec![];
 
   // The name of the node itself will be the first flag
   let first_flag = strip_quotes(node.name().to_string());
@@ -226,9 +712,19 @@ fn parse_flag(
   forms.push(first_flag.clone());
   flag_spans.insert(first_flag, *node.name().span());
 
+  let repeatable = bool_prop(node, "repeatable")?.unwrap_or(false);
+  let persistent = bool_prop(node, "persistent")?.unwrap_or(false);
+  let hidden = bool_prop(node, "hidden")?.unwrap_or(false);
+  let deprecated = bool_prop(node, "deprecated")?.unwrap_or(false);
+
   // The other flags will be parsed as entries
   for flag_entry in node.entries() {
-    if let Some(name) = flag_entry.name() {
+    if flag_entry.name().is_some_and(|name| {
+      ["repeatable", "persistent", "hidden", "deprecated"]
+        .contains(&name.value())
+    }) {
+      continue;
+    } else if let Some(name) = flag_entry.name() {
       return Err(ParseError::InvalidFlag {
         msg: format!("entry with name {name}"),
         span: *flag_entry.span(),
@@ -253,7 +749,10 @@ fn parse_flag(
   }
 
   if let Some(doc) = node.children() {
-    let nodes = get_nodes(doc, &["desc", "type"])?;
+    let nodes = get_nodes(
+      doc,
+      &["desc", "type", "arg", "requires", "conflicts", "aliases"],
+    )?;
 
     if let Some(desc_node) = nodes.get("desc") {
       if desc_node.entries().len() == 1 {
@@ -265,34 +764,277 @@ fn parse_flag(
     }
 
     if let Some(type_node) = nodes.get("type") {
-      if let Some(children) = type_node.children() {
-        let types = children
-          .nodes()
-          .iter()
-          .map(parse_type)
-          .collect::<ParseResult<Vec<ArgType>>>()?;
-
-        if types.len() == 1 {
-          typ = Some(types.into_iter().next().unwrap());
-        } else {
-          typ = Some(ArgType::Any(types));
+      typ = Some(parse_type_node(type_node, defs)?);
+    }
+
+    if let Some(arg_node) = nodes.get("arg") {
+      arg = Some(parse_flag_arg(arg_node)?);
+    }
+
+    if let Some(requires_node) = nodes.get("requires") {
+      requires = strings(requires_node)?;
+    }
+
+    if let Some(conflicts_node) = nodes.get("conflicts") {
+      conflicts = strings(conflicts_node)?;
+    }
+
+    // Aliases can't be shared with other flags either
+    if let Some(aliases_node) = nodes.get("aliases") {
+      aliases = strings(aliases_node)?;
+      for alias in &aliases {
+        if let Some(prev_span) = flag_spans.get(alias) {
+          return Err(ParseError::DuplicateFlag {
+            flag: alias.clone(),
+            span: *aliases_node.span(),
+            prev_span: *prev_span,
+          });
         }
-      } else {
-        return Err(ParseError::EmptyType(*type_node.span()));
+        flag_spans.insert(alias.clone(), *aliases_node.span());
       }
     }
   }
 
-  Ok(Flag { forms, desc, typ })
+  Ok(Flag {
+    forms,
+    desc,
+    typ,
+    arg,
+    requires,
+    conflicts,
+    repeatable,
+    persistent,
+    aliases,
+    hidden,
+    deprecated,
+  })
+}
+
+/// Parse a positional argument inside an `args` node. Arguments are written
+/// like `arg "FILE" optional=true variadic=true { type { path } }`, but a
+/// type on its own (e.g. `path`) can be used for an unnamed, required argument.
+fn parse_arg(node: &KdlNode, defs: &Defs) -> ParseResult<Arg> {
+  if node.name().value() != "arg" {
+    return Ok(Arg::from(parse_type(node, defs)?));
+  }
+
+  let mut arg = Arg {
+    name: None,
+    desc: None,
+    typ: None,
+    optional: bool_prop(node, "optional")?.unwrap_or(false),
+    variadic: bool_prop(node, "variadic")?.unwrap_or(false),
+  };
+  for entry in node.entries() {
+    let value = entry.value();
+    match entry.name().map(|name| name.value()) {
+      None if arg.name.is_none() && value.is_string_value() => {
+        arg.name = value.as_string().map(String::from);
+      }
+      Some("optional" | "variadic") => {}
+      _ => {
+        return Err(ParseError::Generic {
+          error: format!("unexpected entry {entry}"),
+          span: *entry.span(),
+          label: "invalid entry".to_owned(),
+          help: Some(
+            r#"Usage: `arg "NAME" optional=true variadic=true`, all optional"#
+              .to_owned(),
+          ),
+        })
+      }
+    }
+  }
+
+  if let Some(doc) = node.children() {
+    let nodes = get_nodes(doc, &["desc", "type"])?;
+    if let Some(desc_node) = nodes.get("desc") {
+      arg.desc = Some(single_string(desc_node)?);
+    }
+    if let Some(type_node) = nodes.get("type") {
+      arg.typ = Some(parse_type_node(type_node, defs)?);
+    }
+  }
+
+  Ok(arg)
+}
+
+/// Parse a node like `arg "FILE" count="variadic" style="separate"` describing
+/// the values a flag takes. The count defaults to one value.
+fn parse_flag_arg(node: &KdlNode) -> ParseResult<FlagArg> {
+  let mut arg = FlagArg {
+    count: ValueCount::One,
+    name: None,
+    style: ArgStyle::Any,
+  };
+  for entry in node.entries() {
+    let invalid = |error: String| ParseError::Generic {
+      error,
+      span: *entry.span(),
+      label: "invalid entry".to_owned(),
+      help: Some(
+        r#"Usage: `arg "NAME" count="one" style="any"`, all optional"#
+          .to_owned(),
+      ),
+    };
+    let value = entry.value();
+    match entry.name().map(|name| name.value()) {
+      None if arg.name.is_none() && value.is_string_value() => {
+        arg.name = value.as_string().map(String::from);
+      }
+      Some("count") => {
+        arg.count =
+          match (value.as_string(), value.as_i64()) {
+            (Some(count), _) => count.parse().map_err(invalid)?,
+            (_, Some(count)) => usize::try_from(count)
+              .map(ValueCount::Exactly)
+              .map_err(|_| invalid(format!("invalid value count {count}")))?,
+            _ => return Err(invalid(format!("invalid value count {value}"))),
+          };
+      }
+      Some("style") => {
+        arg.style = value
+          .as_string()
+          .ok_or_else(|| invalid(format!("invalid argument style {value}")))?
+          .parse()
+          .map_err(invalid)?;
+      }
+      _ => return Err(invalid(format!("unexpected entry {entry}"))),
+    }
+  }
+  Ok(arg)
+}
+
+/// Parse a node like `type { ... }` whose children are types. If there are
+/// multiple children, it's any of those types.
+fn parse_type_node(node: &KdlNode, defs: &Defs) -> ParseResult<ArgType> {
+  let Some(children) = node.children() else {
+    return Err(ParseError::EmptyType(*node.span()));
+  };
+  let mut types = children
+    .nodes()
+    .iter()
+    .map(|node| parse_type(node, defs))
+    .collect::<ParseResult<Vec<ArgType>>>()?;
+  if types.len() == 1 {
+    Ok(types.remove(0))
+  } else {
+    Ok(ArgType::Any(types))
+  }
 }
 
 /// Helper to treat a node as an [`ArgType`]
-fn parse_type(node: &KdlNode) -> ParseResult<ArgType> {
+fn parse_type(node: &KdlNode, defs: &Defs) -> ParseResult<ArgType> {
   let typ = match node.name().to_string().as_str() {
-    "path" => ArgType::Path,
+    "ref" => {
+      let name = single_string(node)?;
+      let Some(typ) = defs.types.get(&name) else {
+        return Err(ParseError::Generic {
+          error: format!("unknown type {name}"),
+          span: *node.span(),
+          label: "not defined".to_owned(),
+          help: Some("define it inside a top-level types node".to_owned()),
+        });
+      };
+      typ.clone()
+    }
+    "path" => {
+      let mut globs = vec![];
+      for entry in node.entries() {
+        match (
+          entry.name().map(|name| name.value()),
+          entry.value().as_string(),
+        ) {
+          (None, Some(glob)) => globs.push(glob.to_owned()),
+          (Some("dirs"), _) if !globs.is_empty() => {}
+          _ => {
+            return Err(ParseError::Generic {
+              error: format!("unexpected entry {entry}"),
+              span: *entry.span(),
+              label: "invalid entry".to_owned(),
+              help: Some(
+                r#"Usage: `path "*.yaml" "*.yml" dirs=false`, or just `path`"#
+                  .to_owned(),
+              ),
+            })
+          }
+        }
+      }
+      if globs.is_empty() {
+        ArgType::Path
+      } else {
+        ArgType::Glob {
+          globs,
+          dirs: bool_prop(node, "dirs")?.unwrap_or(true),
+        }
+      }
+    }
     "dir" => ArgType::Dir,
     "unknown" => ArgType::Unknown,
     "command" => ArgType::CommandName,
+    "user" => ArgType::User,
+    "group" => ArgType::Group,
+    "host" => ArgType::Host,
+    "pid" => ArgType::Pid,
+    "signal" => ArgType::Signal,
+    "env-var" => ArgType::EnvVar,
+    "interface" => ArgType::NetInterface,
+    "port" => ArgType::Port,
+    "int" => {
+      let (mut min, mut max) = (None, None);
+      for entry in node.entries() {
+        let bound = match entry.name().map(|name| name.value()) {
+          Some("min") => &mut min,
+          Some("max") => &mut max,
+          _ => {
+            return Err(ParseError::Generic {
+              error: format!("unexpected entry {entry}"),
+              span: *entry.span(),
+              label: "invalid entry".to_owned(),
+              help: Some("Usage: `int min=1 max=10`, all optional".to_owned()),
+            })
+          }
+        };
+        *bound =
+          Some(entry.value().as_i64().ok_or_else(|| ParseError::Generic {
+            error: format!("{entry} should be an integer"),
+            span: *entry.span(),
+            label: "expected an integer".to_owned(),
+            help: None,
+          })?);
+      }
+      ArgType::Int { min, max }
+    }
+    "list" => {
+      let mut sep = None;
+      for entry in node.entries() {
+        match (entry.name().map(|name| name.value()), entry.value().as_string())
+        {
+          (Some("sep"), Some(value)) if !value.is_empty() => {
+            sep = Some(value.to_owned());
+          }
+          _ => {
+            return Err(ParseError::Generic {
+              error: format!("unexpected entry {entry}"),
+              span: *entry.span(),
+              label: "invalid entry".to_owned(),
+              help: Some(
+                r#"Usage: `list sep="," { ... }`, with the type of each value inside"#
+                  .to_owned(),
+              ),
+            })
+          }
+        }
+      }
+      ArgType::List {
+        typ: Box::new(parse_type_node(node, defs)?),
+        sep: sep.unwrap_or_else(|| ",".to_owned()),
+      }
+    }
+    "float" => ArgType::Float,
+    "duration" => ArgType::Duration,
+    "url" => ArgType::Url,
+    "string" => ArgType::Text,
     "strings" => {
       if !node.entries().is_empty() {
         return Err(ParseError::Generic {
@@ -327,17 +1069,34 @@ fn parse_type(node: &KdlNode) -> ParseResult<ArgType> {
       }
     }
     "run" => {
-      if node.entries().is_empty() {
+      let mut words = Vec::new();
+      let mut sep = None;
+      for entry in node.entries() {
+        match (entry.name().map(|name| name.value()), entry.value().as_string())
+        {
+          (None, _) => words.push(strip_quotes(entry.to_string())),
+          (Some("sep"), Some(value)) if !value.is_empty() => {
+            sep = Some(value.to_owned());
+          }
+          _ => {
+            return Err(ParseError::Generic {
+              error: format!("unexpected entry {entry}"),
+              span: *entry.span(),
+              label: "invalid entry".to_owned(),
+              help: Some(
+                r#"Usage: `run "git branch" sep="\t"`, where sep separates each value from its description"#
+                  .to_owned(),
+              ),
+            })
+          }
+        }
+      }
+      if words.is_empty() {
         return Err(ParseError::MissingCommand(*node.name().span()));
       }
       ArgType::Run {
-        cmd: node
-          .entries()
-          .iter()
-          .map(|entry| strip_quotes(entry.to_string()))
-          .collect::<Vec<_>>()
-          .join(" "),
-        sep: None,
+        cmd: words.join(" "),
+        sep,
       }
     }
     // todo handle other variants
@@ -398,8 +1157,16 @@ fn strip_quotes(flag: impl AsRef<str>) -> String {
 
 #[cfg(test)]
 mod tests {
-  use super::parse_from_str;
-  use crate::{ArgType, CommandInfo, Flag};
+  use std::path::Path;
+
+  use super::{
+    parse_all_from_str, parse_from_str, parse_overlay_from_str, KdlDeserError,
+    ParseError,
+  };
+  use crate::{
+    parse_deser::overlay::{FlagPatch, Overlay},
+    Arg, ArgStyle, ArgType, CommandInfo, Flag, FlagArg, ValueCount,
+  };
 
   #[test]
   fn test1() -> miette::Result<()> {
@@ -411,9 +1178,10 @@ mod tests {
           forms: vec!["--help".to_string(), "-h".to_string()],
           desc: Some("Show help output".to_string()),
           typ: Some(ArgType::Path),
+          ..Default::default()
         }],
-        args: vec![ArgType::Dir],
-        subcommands: vec![]
+        args: vec![ArgType::Dir.into()],
+        ..Default::default()
       },
       parse_from_str(
         r#"
@@ -431,7 +1199,8 @@ mod tests {
           }
           desc "foo bar baz"
         }
-      "#
+      "#,
+        Path::new("")
       )?
     );
     Ok(())
@@ -442,10 +1211,8 @@ mod tests {
     assert_eq!(
       CommandInfo {
         name: "foo".to_string(),
-        desc: None,
         flags: vec![Flag {
           forms: vec!["--some-flag".to_owned()],
-          desc: None,
           typ: Some(ArgType::Any(vec![
             ArgType::Path,
             ArgType::Dir,
@@ -459,9 +1226,9 @@ mod tests {
             },
             ArgType::Unknown,
           ])),
+          ..Default::default()
         }],
-        args: vec![],
-        subcommands: vec![]
+        ..Default::default()
       },
       parse_from_str(
         r#"
@@ -481,9 +1248,535 @@ mod tests {
             }
           }
         }
+      "#,
+        Path::new("")
+      )?
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn type_and_flag_refs() -> miette::Result<()> {
+    let text = r#"
+      types {
+        namespace {
+          run "kubectl get ns -o name"
+        }
+        file-or-ns {
+          path
+          ref "namespace"
+        }
+      }
+      flag-groups {
+        common {
+          "--verbose"
+          "--namespace" {
+            type {
+              ref "namespace"
+            }
+          }
+        }
+      }
+      kubectl {
+        flags {
+          ref "common"
+          "-f" {
+            type {
+              ref "file-or-ns"
+            }
+          }
+        }
+        args {
+          ref "namespace"
+        }
+      }
+    "#;
+    let namespace = ArgType::Run {
+      cmd: "kubectl get ns -o name".to_owned(),
+      sep: None,
+    };
+    assert_eq!(
+      CommandInfo {
+        name: "kubectl".to_owned(),
+        flags: vec![
+          Flag {
+            forms: vec!["--verbose".to_owned()],
+            ..Default::default()
+          },
+          Flag {
+            forms: vec!["--namespace".to_owned()],
+            typ: Some(namespace.clone()),
+            ..Default::default()
+          },
+          Flag {
+            forms: vec!["-f".to_owned()],
+            typ: Some(ArgType::Any(vec![ArgType::Path, namespace.clone()])),
+            ..Default::default()
+          },
+        ],
+        args: vec![namespace.into()],
+        ..Default::default()
+      },
+      parse_from_str(text, Path::new(""))?
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn directive_names_as_commands() -> miette::Result<()> {
+    let text = r#"
+      types {
+        desc "Manage types"
+      }
+      include hidden=true
+      flag-groups
+    "#;
+    let cmds = parse_all_from_str(text, Path::new(""))?;
+    assert_eq!(
+      vec![
+        CommandInfo {
+          name: "types".to_owned(),
+          desc: Some("Manage types".to_owned()),
+          ..Default::default()
+        },
+        CommandInfo {
+          name: "include".to_owned(),
+          hidden: true,
+          ..Default::default()
+        },
+        CommandInfo {
+          name: "flag-groups".to_owned(),
+          ..Default::default()
+        },
+      ],
+      cmds
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn duplicate_alias_from_ref() {
+    let text = r#"
+      flag-groups {
+        common {
+          "--color" {
+            aliases "--colour"
+          }
+        }
+      }
+      foo {
+        flags {
+          ref "common"
+          "--colour"
+        }
+      }
+    "#;
+    assert!(matches!(
+      parse_from_str(text, Path::new("")),
+      Err(KdlDeserError::ParseError {
+        error: ParseError::DuplicateFlag { flag, .. },
+        ..
+      }) if flag == "--colour"
+    ));
+  }
+
+  #[test]
+  fn unknown_ref() {
+    let text = r#"
+      foo {
+        flags {
+          "--bar" {
+            type {
+              ref "nope"
+            }
+          }
+        }
+      }
+    "#;
+    assert!(matches!(
+      parse_from_str(text, Path::new("")),
+      Err(KdlDeserError::ParseError {
+        error: ParseError::Generic { error, .. },
+        ..
+      }) if error == "unknown type nope"
+    ));
+  }
+
+  #[test]
+  fn flag_args() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        flags {
+          "--exclude" {
+            arg "PAT" count="variadic" style="separate"
+          }
+          "--point" {
+            arg count=2
+          }
+          "--color" {
+            arg "WHEN" count="optional" style="attached"
+          }
+        }
+      }
+    "#;
+    let flag = |form: &str, count, name: Option<&str>, style| Flag {
+      forms: vec![form.to_owned()],
+      arg: Some(FlagArg {
+        count,
+        name: name.map(String::from),
+        style,
+      }),
+      ..Default::default()
+    };
+    assert_eq!(
+      vec![
+        flag(
+          "--exclude",
+          ValueCount::Variadic,
+          Some("PAT"),
+          ArgStyle::Separate
+        ),
+        flag("--point", ValueCount::Exactly(2), None, ArgStyle::Any),
+        flag(
+          "--color",
+          ValueCount::Optional,
+          Some("WHEN"),
+          ArgStyle::Attached
+        ),
+      ],
+      parse_from_str(text, Path::new(""))?.flags
+    );
+
+    let bad = r#"
+      foo {
+        flags {
+          "-x" {
+            arg count="lots"
+          }
+        }
+      }
+    "#;
+    assert!(matches!(
+      parse_from_str(bad, Path::new("")),
+      Err(KdlDeserError::ParseError {
+        error: ParseError::Generic { error, .. },
+        ..
+      }) if error.starts_with("invalid value count lots")
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn flag_relations() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        flags {
+          "--key" {
+            requires "--cert"
+            conflicts "--insecure" "-k"
+          }
+        }
+        exclusive {
+          "--json" "--yaml"
+          "-q"
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert_eq!(vec!["--cert"], cmd.flags[0].requires);
+    assert_eq!(vec!["--insecure", "-k"], cmd.flags[0].conflicts);
+    assert_eq!(vec![vec!["--json", "--yaml"], vec!["-q"]], cmd.exclusive);
+    Ok(())
+  }
+
+  #[test]
+  fn repeatable_flags() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        flags {
+          "-v" "--verbose" repeatable=true
+          "-q"
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert_eq!(vec!["-v", "--verbose"], cmd.flags[0].forms);
+    assert!(cmd.flags[0].repeatable);
+    assert!(!cmd.flags[1].repeatable);
+
+    let bad = r#"
+      foo {
+        flags {
+          "-v" repeatable="yes"
+        }
+      }
+    "#;
+    assert!(parse_from_str(bad, Path::new("")).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn persistent_flags() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        flags {
+          "--config" persistent=true
+          "-q"
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert!(cmd.flags[0].persistent);
+    assert!(!cmd.flags[1].persistent);
+    Ok(())
+  }
+
+  #[test]
+  fn positional_args() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        args {
+          arg "SRC" variadic=true {
+            desc "Files to copy"
+            type {
+              path
+            }
+          }
+          arg "DEST" optional=true
+          dir
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert_eq!(
+      vec![
+        Arg {
+          name: Some("SRC".to_owned()),
+          desc: Some("Files to copy".to_owned()),
+          typ: Some(ArgType::Path),
+          optional: false,
+          variadic: true,
+        },
+        Arg {
+          name: Some("DEST".to_owned()),
+          desc: None,
+          typ: None,
+          optional: true,
+          variadic: false,
+        },
+        Arg::from(ArgType::Dir),
+      ],
+      cmd.args
+    );
+
+    let bad = r#"foo { args { arg "A" "B" } }"#;
+    assert!(parse_from_str(bad, Path::new("")).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn glob_paths() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        args {
+          path "*.yaml" "*.yml"
+          path "*.tar" dirs=false
+          path
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert_eq!(
+      vec![
+        Arg::from(ArgType::Glob {
+          globs: vec!["*.yaml".to_owned(), "*.yml".to_owned()],
+          dirs: true,
+        }),
+        Arg::from(ArgType::Glob {
+          globs: vec!["*.tar".to_owned()],
+          dirs: false,
+        }),
+        Arg::from(ArgType::Path),
+      ],
+      cmd.args
+    );
+
+    let bad = r#"foo { args { path dirs=false } }"#;
+    assert!(parse_from_str(bad, Path::new("")).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn list_types() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        args {
+          list {
+            user
+          }
+          list sep=":" {
+            dir
+          }
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert_eq!(
+      vec![
+        Arg::from(ArgType::List {
+          typ: Box::new(ArgType::User),
+          sep: ",".to_owned(),
+        }),
+        Arg::from(ArgType::List {
+          typ: Box::new(ArgType::Dir),
+          sep: ":".to_owned(),
+        }),
+      ],
+      cmd.args
+    );
+
+    let bad = r#"foo { args { list sep="" { user } } }"#;
+    assert!(parse_from_str(bad, Path::new("")).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn aliases_and_hidden() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        flags {
+          "--color" deprecated=true {
+            aliases "--colour"
+          }
+          "--debug" hidden=true
+        }
+        subcommands {
+          remove {
+            aliases "rm" "del"
+          }
+          internal hidden=true
+        }
+      }
+    "#;
+    let cmd = parse_from_str(text, Path::new(""))?;
+    assert_eq!(vec!["--colour"], cmd.flags[0].aliases);
+    assert!(cmd.flags[0].deprecated && !cmd.flags[0].hidden);
+    assert!(cmd.flags[1].hidden);
+    assert_eq!(vec!["rm", "del"], cmd.subcommands[0].aliases);
+    assert!(cmd.subcommands[1].hidden && !cmd.subcommands[1].deprecated);
+
+    let dup = r#"
+      foo {
+        flags {
+          "--color" {
+            aliases "--colour"
+          }
+          "--colour"
+        }
+      }
+    "#;
+    assert!(matches!(
+      parse_from_str(dup, Path::new("")),
+      Err(KdlDeserError::ParseError {
+        error: ParseError::DuplicateFlag { .. },
+        ..
+      })
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn multiple_commands() -> miette::Result<()> {
+    let text = r#"
+      foo {
+        desc "Foo"
+      }
+      bar
+    "#;
+    let cmds = parse_all_from_str(text, Path::new(""))?;
+    assert_eq!(
+      vec!["foo", "bar"],
+      cmds.iter().map(|cmd| cmd.name.as_str()).collect::<Vec<_>>()
+    );
+    assert!(matches!(
+      parse_from_str(text, Path::new("")),
+      Err(KdlDeserError::TooManyNodes(2))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn parse_overlay() -> miette::Result<()> {
+    assert_eq!(
+      Overlay {
+        name: "git".to_owned(),
+        remove_flags: vec!["--bogus".to_owned(), "-x".to_owned()],
+        subcommands: vec![Overlay {
+          name: "checkout".to_owned(),
+          rename: Some("co".to_owned()),
+          flags: vec![FlagPatch {
+            forms: vec!["-b".to_owned()],
+            desc: None,
+            typ: Some(ArgType::Run {
+              cmd: "git branch".to_owned(),
+              sep: None
+            }),
+            arg: None,
+            replace: true,
+            requires: None,
+            conflicts: None,
+            repeatable: None,
+            persistent: None,
+            aliases: None,
+            hidden: None,
+            deprecated: None,
+          }],
+          ..Default::default()
+        }],
+        remove_subcommands: vec!["gui".to_owned()],
+        ..Default::default()
+      },
+      parse_overlay_from_str(
+        r#"
+        git {
+          remove-flags "--bogus" "-x"
+          subcommands {
+            checkout {
+              rename "co"
+              flags {
+                "-b" replace=true {
+                  type {
+                    run "git branch"
+                  }
+                }
+              }
+            }
+          }
+          remove-subcommands "gui"
+        }
       "#
       )?
     );
     Ok(())
   }
+
+  #[test]
+  fn overlay_clears_lists() -> miette::Result<()> {
+    let overlay = parse_overlay_from_str(
+      r#"
+      git {
+        flags {
+          "-b" {
+            requires
+            aliases
+          }
+        }
+      }
+    "#,
+    )?;
+    assert_eq!(Some(vec![]), overlay.flags[0].requires);
+    assert_eq!(None, overlay.flags[0].conflicts);
+    assert_eq!(Some(vec![]), overlay.flags[0].aliases);
+    Ok(())
+  }
 }
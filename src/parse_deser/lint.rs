@@ -0,0 +1,835 @@
+//! For finding problems in config files that aren't caught when deserializing
+//! them, e.g. flags without dashes or types that won't complete anything
+//!
+//! For KDL, the problems are reported with spans pointing to where they are.
+//! For other formats, spans aren't kept track of after deserializing, so
+//! problems there only have messages.
+
+use std::{env, path::Path};
+
+use kdl::{KdlDocument, KdlNode};
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use super::{
+  error::DeserError, kdl::is_directive, parse_all_from_str_in, InputFormat,
+};
+use crate::{Arg, ArgType, CommandInfo, Flag};
+
+/// A problem found in a config file
+#[derive(Debug, Diagnostic, Error)]
+pub enum Lint {
+  #[error("flag {form} doesn't start with a dash")]
+  #[diagnostic(
+    code(gen_completions::lint::flag_without_dash),
+    url(docsrs),
+    help("write it as -{form} or --{form}")
+  )]
+  FlagWithoutDash {
+    form: String,
+    #[label("should start with - or --")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("subcommand name {name:?} has whitespace in it")]
+  #[diagnostic(
+    code(gen_completions::lint::subcommand_whitespace),
+    url(docsrs),
+    help("nested subcommands go inside the parent's subcommands node")
+  )]
+  SubcommandWhitespace {
+    name: String,
+    #[label("should be a single word")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("duplicate subcommand {name}")]
+  #[diagnostic(
+    code(gen_completions::lint::duplicate_subcommand),
+    url(docsrs),
+    help("merge the {name} subcommands together")
+  )]
+  DuplicateSubcommand {
+    name: String,
+    #[label("duplicate subcommand")]
+    span: Option<SourceSpan>,
+    #[label("already given here")]
+    prev_span: Option<SourceSpan>,
+  },
+
+  #[error("{form} isn't one of the command's flags")]
+  #[diagnostic(
+    code(gen_completions::lint::unknown_flag),
+    url(docsrs),
+    help("refer to flags by one of their forms, e.g. --verbose")
+  )]
+  UnknownFlag {
+    form: String,
+    #[label("no flag with this form")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("argument {name} is in the wrong place")]
+  #[diagnostic(
+    code(gen_completions::lint::misplaced_arg),
+    url(docsrs),
+    help("{reason}")
+  )]
+  MisplacedArg {
+    name: String,
+    reason: String,
+    #[label("can't go here")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("strings type is empty")]
+  #[diagnostic(
+    code(gen_completions::lint::empty_strings),
+    url(docsrs),
+    severity(Warning),
+    help("add some strings or remove the type")
+  )]
+  EmptyStrings(#[label("won't complete anything")] Option<SourceSpan>),
+
+  #[error("int type has no valid values")]
+  #[diagnostic(
+    code(gen_completions::lint::empty_range),
+    url(docsrs),
+    severity(Warning),
+    help("min ({min}) should be at most max ({max})")
+  )]
+  EmptyRange {
+    min: i64,
+    max: i64,
+    #[label("min is greater than max")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("command {binary} not found")]
+  #[diagnostic(
+    code(gen_completions::lint::missing_binary),
+    url(docsrs),
+    severity(Warning)
+  )]
+  MissingBinary {
+    binary: String,
+    #[label("not found in $PATH")]
+    span: Option<SourceSpan>,
+  },
+
+  #[error("{text:?} can't be escaped in all shells")]
+  #[diagnostic(
+    code(gen_completions::lint::unescapable),
+    url(docsrs),
+    severity(Warning)
+  )]
+  Unescapable {
+    text: String,
+    reason: String,
+    #[label("{reason}")]
+    span: Option<SourceSpan>,
+  },
+}
+
+/// Check a config file for problems. Files included from KDL are looked for
+/// relative to the current directory.
+///
+/// # Errors
+///
+/// Fails if the text can't be deserialized in the first place
+pub fn lint_str(
+  text: &str,
+  format: InputFormat,
+) -> std::result::Result<Vec<Lint>, DeserError> {
+  lint_str_in(text, format, Path::new(""))
+}
+
+/// Like [`lint_str`], but KDL includes are resolved relative to `dir`. Only
+/// the definitions in the file itself are checked, not those in included
+/// files.
+pub(super) fn lint_str_in(
+  text: &str,
+  format: InputFormat,
+  dir: &Path,
+) -> std::result::Result<Vec<Lint>, DeserError> {
+  let cmds = parse_all_from_str_in(text, format, dir)?;
+  let mut lints = Vec::new();
+  match format {
+    InputFormat::Kdl => {
+      // This was already parsed above, so it's valid
+      let doc: KdlDocument = text.parse().expect("KDL should be valid");
+      // The commands were parsed in the same order as their nodes
+      let mut cmds = cmds.iter();
+      for node in doc.nodes() {
+        let directive = is_directive(node);
+        match node.name().value() {
+          "include" if directive => {}
+          "types" if directive => {
+            for def in node.children().map_or(&[][..], KdlDocument::nodes) {
+              for type_node in
+                def.children().map_or(&[][..], KdlDocument::nodes)
+              {
+                lint_kdl_type(type_node, &mut lints);
+              }
+            }
+          }
+          "flag-groups" if directive => {
+            for group in node.children().map_or(&[][..], KdlDocument::nodes) {
+              if let Some(flags) = group.children() {
+                // Flag groups can be used in any command, so references to
+                // other flags can't be checked here
+                lint_kdl_flags(flags, None, &mut lints);
+              }
+            }
+          }
+          _ => {
+            let cmd = cmds.next().expect("Command should've been parsed");
+            lint_kdl_cmd(node, cmd, &[], &mut lints);
+          }
+        }
+      }
+    }
+    InputFormat::Json | InputFormat::Toml | InputFormat::Yaml => {
+      for cmd in &cmds {
+        lint_cmd(cmd, &[], &mut lints);
+      }
+    }
+  }
+  Ok(lints)
+}
+
+/// Check a KDL node for a command. The node's already known to match the
+/// shape of a [`CommandInfo`]. `inherited` holds the persistent flags of the
+/// command's ancestors.
+fn lint_kdl_cmd(
+  node: &KdlNode,
+  cmd: &CommandInfo,
+  inherited: &[&Flag],
+  lints: &mut Vec<Lint>,
+) {
+  let Some(doc) = node.children() else {
+    return;
+  };
+  let known = known_flags(cmd, inherited);
+
+  for child in doc.nodes() {
+    match child.name().value() {
+      "desc" => {
+        for entry in child.entries() {
+          if let Some(desc) = entry.value().as_string() {
+            check_desc(desc, true, Some(*entry.span()), lints);
+          }
+        }
+      }
+      "flags" => {
+        if let Some(flags) = child.children() {
+          lint_kdl_flags(flags, Some(&known), lints);
+        }
+      }
+      "exclusive" => {
+        for group in child.children().map_or(&[][..], KdlDocument::nodes) {
+          check_flag_ref(
+            group.name().value(),
+            &known,
+            Some(*group.name().span()),
+            lints,
+          );
+          for entry in group.entries() {
+            if let Some(form) = entry.value().as_string() {
+              check_flag_ref(form, &known, Some(*entry.span()), lints);
+            }
+          }
+        }
+      }
+      "args" => {
+        let arg_nodes = child.children().map_or(&[][..], KdlDocument::nodes);
+        for arg_node in arg_nodes {
+          if arg_node.name().value() == "arg" {
+            lint_kdl_arg(arg_node, lints);
+          } else {
+            lint_kdl_type(arg_node, lints);
+          }
+        }
+        let spans = arg_nodes.iter().map(|node| Some(*node.span()));
+        check_args(&cmd.args, spans, lints);
+      }
+      "aliases" => {
+        for entry in child.entries() {
+          if let Some(alias) = entry.value().as_string() {
+            check_subcmd_name(alias, Some(*entry.span()), lints);
+          }
+        }
+      }
+      "subcommands" => {
+        let inherited = inherited_flags(cmd, inherited);
+        let subcmds = child.children().map_or(&[][..], KdlDocument::nodes);
+        for (i, subcmd) in subcmds.iter().enumerate() {
+          let name = subcmd.name().value();
+          let span = Some(*subcmd.name().span());
+          check_subcmd_name(name, span, lints);
+          if let Some(prev) =
+            subcmds[..i].iter().find(|prev| prev.name().value() == name)
+          {
+            lints.push(Lint::DuplicateSubcommand {
+              name: name.to_string(),
+              span,
+              prev_span: Some(*prev.name().span()),
+            });
+          }
+          lint_kdl_cmd(subcmd, &cmd.subcommands[i], &inherited, lints);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+/// Check the children of a `flags` node. If the flags that can be referred to
+/// are known, references to other flags are checked too.
+fn lint_kdl_flags(
+  doc: &KdlDocument,
+  known: Option<&[&Flag]>,
+  lints: &mut Vec<Lint>,
+) {
+  for flag_node in doc.nodes() {
+    // References to flag groups aren't flags themselves
+    if flag_node.name().value() == "ref" {
+      continue;
+    }
+    check_form(
+      flag_node.name().value(),
+      Some(*flag_node.name().span()),
+      lints,
+    );
+    for entry in flag_node.entries() {
+      if let Some(form) = entry.value().as_string() {
+        check_form(form, Some(*entry.span()), lints);
+      }
+    }
+    lint_kdl_flag(flag_node, known, lints);
+  }
+}
+
+fn lint_kdl_flag(
+  node: &KdlNode,
+  known: Option<&[&Flag]>,
+  lints: &mut Vec<Lint>,
+) {
+  for child in node.children().map_or(&[][..], KdlDocument::nodes) {
+    match child.name().value() {
+      "desc" => {
+        for entry in child.entries() {
+          if let Some(desc) = entry.value().as_string() {
+            check_desc(desc, false, Some(*entry.span()), lints);
+          }
+        }
+      }
+      "type" => {
+        for type_node in child.children().map_or(&[][..], KdlDocument::nodes) {
+          lint_kdl_type(type_node, lints);
+        }
+      }
+      "aliases" => {
+        for entry in child.entries() {
+          if let Some(form) = entry.value().as_string() {
+            check_form(form, Some(*entry.span()), lints);
+          }
+        }
+      }
+      "requires" | "conflicts" => {
+        if let Some(known) = known {
+          for entry in child.entries() {
+            if let Some(form) = entry.value().as_string() {
+              check_flag_ref(form, known, Some(*entry.span()), lints);
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+fn lint_kdl_arg(node: &KdlNode, lints: &mut Vec<Lint>) {
+  for child in node.children().map_or(&[][..], KdlDocument::nodes) {
+    match child.name().value() {
+      "desc" => {
+        for entry in child.entries() {
+          if let Some(desc) = entry.value().as_string() {
+            check_desc(desc, false, Some(*entry.span()), lints);
+          }
+        }
+      }
+      "type" => {
+        for type_node in child.children().map_or(&[][..], KdlDocument::nodes) {
+          lint_kdl_type(type_node, lints);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+fn lint_kdl_type(node: &KdlNode, lints: &mut Vec<Lint>) {
+  match node.name().value() {
+    "strings" => {
+      let strings = node.children().map_or(&[][..], KdlDocument::nodes);
+      if strings.is_empty() {
+        lints.push(Lint::EmptyStrings(Some(*node.span())));
+      }
+      for string in strings {
+        check_string(string.name().value(), Some(*string.name().span()), lints);
+        for entry in string.entries() {
+          if let Some(desc) = entry.value().as_string() {
+            check_string(desc, Some(*entry.span()), lints);
+          }
+        }
+      }
+    }
+    "int" => {
+      let bound =
+        |name| node.get(name).and_then(|entry| entry.value().as_i64());
+      if let (Some(min), Some(max)) = (bound("min"), bound("max")) {
+        check_range(min, max, Some(*node.span()), lints);
+      }
+    }
+    "list" => {
+      for type_node in node.children().map_or(&[][..], KdlDocument::nodes) {
+        lint_kdl_type(type_node, lints);
+      }
+    }
+    "run" => {
+      if let Some(entry) = node.entries().first() {
+        if let Some(cmd) = entry.value().as_string() {
+          check_run(cmd, Some(*entry.span()), lints);
+        }
+      }
+    }
+    _ => {}
+  }
+}
+
+/// Check a command that came from a format without spans. `inherited` holds
+/// the persistent flags of the command's ancestors.
+fn lint_cmd(cmd: &CommandInfo, inherited: &[&Flag], lints: &mut Vec<Lint>) {
+  let known = known_flags(cmd, inherited);
+  if let Some(desc) = &cmd.desc {
+    check_desc(desc, true, None, lints);
+  }
+  for Flag {
+    forms,
+    desc,
+    typ,
+    requires,
+    conflicts,
+    aliases,
+    ..
+  } in &cmd.flags
+  {
+    for form in requires.iter().chain(conflicts) {
+      check_flag_ref(form, &known, None, lints);
+    }
+    for form in forms.iter().chain(aliases) {
+      check_form(form, None, lints);
+    }
+    if let Some(desc) = desc {
+      check_desc(desc, false, None, lints);
+    }
+    if let Some(typ) = typ {
+      lint_type(typ, lints);
+    }
+  }
+  for form in cmd.exclusive.iter().flatten() {
+    check_flag_ref(form, &known, None, lints);
+  }
+  for arg in &cmd.args {
+    if let Some(desc) = &arg.desc {
+      check_desc(desc, false, None, lints);
+    }
+    if let Some(typ) = &arg.typ {
+      lint_type(typ, lints);
+    }
+  }
+  check_args(&cmd.args, std::iter::repeat(None), lints);
+  let inherited = inherited_flags(cmd, inherited);
+  for (i, subcmd) in cmd.subcommands.iter().enumerate() {
+    for name in std::iter::once(&subcmd.name).chain(&subcmd.aliases) {
+      check_subcmd_name(name, None, lints);
+    }
+    if cmd.subcommands[..i]
+      .iter()
+      .any(|prev| prev.name == subcmd.name)
+    {
+      lints.push(Lint::DuplicateSubcommand {
+        name: subcmd.name.clone(),
+        span: None,
+        prev_span: None,
+      });
+    }
+    lint_cmd(subcmd, &inherited, lints);
+  }
+}
+
+fn lint_type(typ: &ArgType, lints: &mut Vec<Lint>) {
+  match typ {
+    ArgType::Strings(strings) => {
+      if strings.is_empty() {
+        lints.push(Lint::EmptyStrings(None));
+      }
+      for (value, desc) in strings {
+        check_string(value, None, lints);
+        if let Some(desc) = desc {
+          check_string(desc, None, lints);
+        }
+      }
+    }
+    ArgType::Run { cmd, .. } => check_run(cmd, None, lints),
+    ArgType::Int {
+      min: Some(min),
+      max: Some(max),
+    } => check_range(*min, *max, None, lints),
+    ArgType::Any(types) => {
+      for typ in types {
+        lint_type(typ, lints);
+      }
+    }
+    ArgType::List { typ, .. } => lint_type(typ, lints),
+    _ => {}
+  }
+}
+
+fn check_range(
+  min: i64,
+  max: i64,
+  span: Option<SourceSpan>,
+  lints: &mut Vec<Lint>,
+) {
+  if min > max {
+    lints.push(Lint::EmptyRange { min, max, span });
+  }
+}
+
+fn check_form(form: &str, span: Option<SourceSpan>, lints: &mut Vec<Lint>) {
+  if !form.starts_with('-') {
+    lints.push(Lint::FlagWithoutDash {
+      form: form.to_string(),
+      span,
+    });
+  }
+}
+
+/// Only the last argument can be variadic, and optional arguments can only be
+/// followed by other optional arguments
+fn check_args(
+  args: &[Arg],
+  spans: impl Iterator<Item = Option<SourceSpan>>,
+  lints: &mut Vec<Lint>,
+) {
+  let mut seen_optional = false;
+  for (i, (arg, span)) in args.iter().zip(spans).enumerate() {
+    let reason = if arg.variadic && i + 1 < args.len() {
+      Some("only the last argument can be variadic")
+    } else if seen_optional && !arg.optional {
+      Some("required arguments can't come after optional ones")
+    } else {
+      None
+    };
+    if let Some(reason) = reason {
+      lints.push(Lint::MisplacedArg {
+        name: arg.name.clone().unwrap_or_else(|| format!("#{}", i + 1)),
+        reason: reason.to_owned(),
+        span,
+      });
+    }
+    seen_optional |= arg.optional;
+  }
+}
+
+/// The flags that a command's flags can refer to: its own flags and the
+/// persistent flags it inherits
+fn known_flags<'a>(
+  cmd: &'a CommandInfo,
+  inherited: &[&'a Flag],
+) -> Vec<&'a Flag> {
+  cmd.flags.iter().chain(inherited.iter().copied()).collect()
+}
+
+/// The persistent flags passed down to a command's subcommands
+fn inherited_flags<'a>(
+  cmd: &'a CommandInfo,
+  inherited: &[&'a Flag],
+) -> Vec<&'a Flag> {
+  inherited
+    .iter()
+    .copied()
+    .chain(cmd.flags.iter().filter(|flag| flag.persistent))
+    .collect()
+}
+
+/// Make sure a flag referred to by `requires`, `conflicts`, or `exclusive`
+/// actually exists
+fn check_flag_ref(
+  form: &str,
+  known: &[&Flag],
+  span: Option<SourceSpan>,
+  lints: &mut Vec<Lint>,
+) {
+  if !known
+    .iter()
+    .any(|flag| flag.forms.iter().chain(&flag.aliases).any(|f| f == form))
+  {
+    lints.push(Lint::UnknownFlag {
+      form: form.to_owned(),
+      span,
+    });
+  }
+}
+
+fn check_subcmd_name(
+  name: &str,
+  span: Option<SourceSpan>,
+  lints: &mut Vec<Lint>,
+) {
+  if name.contains(char::is_whitespace) {
+    lints.push(Lint::SubcommandWhitespace {
+      name: name.to_string(),
+      span,
+    });
+  }
+}
+
+/// Command descriptions can have multiple lines, but flag descriptions end up
+/// in places like Nushell comments, where newlines can't go
+fn check_desc(
+  desc: &str,
+  allow_newlines: bool,
+  span: Option<SourceSpan>,
+  lints: &mut Vec<Lint>,
+) {
+  let bad_char = desc
+    .chars()
+    .find(|&c| c.is_control() && !(allow_newlines && c == '\n'));
+  if let Some(c) = bad_char {
+    lints.push(Lint::Unescapable {
+      text: desc.to_string(),
+      reason: format!("contains control character {c:?}"),
+      span,
+    });
+  }
+}
+
+/// Check a value (or its description) for the `strings` type. These get put
+/// inside single quotes for Nushell and are separated by tabs for Carapace.
+fn check_string(text: &str, span: Option<SourceSpan>, lints: &mut Vec<Lint>) {
+  let reason = if text.contains('\'') {
+    Some("contains a single quote, which Nushell strings can't escape")
+  } else if text.chars().any(char::is_control) {
+    Some("contains a control character")
+  } else {
+    None
+  };
+  if let Some(reason) = reason {
+    lints.push(Lint::Unescapable {
+      text: text.to_string(),
+      reason: reason.to_owned(),
+      span,
+    });
+  }
+}
+
+/// Check that the program a `run` type runs exists
+fn check_run(cmd: &str, span: Option<SourceSpan>, lints: &mut Vec<Lint>) {
+  let Some(binary) = cmd.split_whitespace().next() else {
+    return;
+  };
+  let found = if binary.contains('/') {
+    Path::new(binary).is_file()
+  } else {
+    env::var_os("PATH").is_some_and(|path| {
+      env::split_paths(&path).any(|dir| dir.join(binary).is_file())
+    })
+  };
+  if !found {
+    lints.push(Lint::MissingBinary {
+      binary: binary.to_string(),
+      span,
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{lint_str, Lint};
+  use crate::parse_deser::InputFormat;
+
+  #[test]
+  fn test_kdl() {
+    let text = r#"
+      foo {
+        flags {
+          verbose
+          "-o" {
+            type {
+              strings {
+              }
+              run "definitely-not-a-real-command --foo"
+            }
+          }
+        }
+        subcommands {
+          "bar baz"
+          qux
+          qux
+        }
+      }
+    "#;
+    let lints = lint_str(text, InputFormat::Kdl).unwrap();
+    assert!(matches!(
+      lints.as_slice(),
+      [
+        Lint::FlagWithoutDash { form, span: Some(_) },
+        Lint::EmptyStrings(Some(_)),
+        Lint::MissingBinary { binary, span: Some(_) },
+        Lint::SubcommandWhitespace { span: Some(_), .. },
+        Lint::DuplicateSubcommand { span: Some(_), prev_span: Some(_), .. },
+      ] if form == "verbose" && binary == "definitely-not-a-real-command"
+    ));
+  }
+
+  #[test]
+  fn flag_refs() {
+    let text = r#"
+      foo {
+        flags {
+          "--json"
+          "--yaml"
+          "--key" {
+            requires "--cert"
+            conflicts "--json"
+          }
+        }
+        exclusive {
+          "--json" "--yaml" "--toml"
+        }
+      }
+    "#;
+    let lints = lint_str(text, InputFormat::Kdl).unwrap();
+    let forms = lints
+      .iter()
+      .map(|lint| match lint {
+        Lint::UnknownFlag {
+          form,
+          span: Some(_),
+        } => form.as_str(),
+        lint => panic!("unexpected lint {lint:?}"),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(vec!["--cert", "--toml"], forms);
+
+    let text = r#"{
+      "name": "foo",
+      "flags": [{"forms": ["-a"], "conflicts": ["-b"]}],
+      "exclusive": [["-a", "-c"]]
+    }"#;
+    let lints = lint_str(text, InputFormat::Json).unwrap();
+    assert!(matches!(
+      lints.as_slice(),
+      [
+        Lint::UnknownFlag { form: b, span: None },
+        Lint::UnknownFlag { form: c, span: None },
+      ] if b == "-b" && c == "-c"
+    ));
+  }
+
+  #[test]
+  fn inherited_flag_refs() {
+    let text = r#"
+      foo {
+        flags {
+          "--config" persistent=true
+          "--help"
+        }
+        subcommands {
+          bar {
+            flags {
+              "--color" {
+                aliases "--colour"
+              }
+              "--plain" {
+                conflicts "--colour" "--config" "--help"
+              }
+            }
+            subcommands {
+              baz {
+                exclusive {
+                  "--config" "--color"
+                }
+              }
+            }
+          }
+        }
+      }
+    "#;
+    let lints = lint_str(text, InputFormat::Kdl).unwrap();
+    let forms = lints
+      .iter()
+      .map(|lint| match lint {
+        Lint::UnknownFlag { form, .. } => form.as_str(),
+        lint => panic!("unexpected lint {lint:?}"),
+      })
+      .collect::<Vec<_>>();
+    // --help isn't persistent, and --color isn't inherited by baz
+    assert_eq!(vec!["--help", "--color"], forms);
+  }
+
+  #[test]
+  fn misplaced_args() {
+    let text = r#"
+      foo {
+        args {
+          arg "REST" variadic=true
+          arg "OPT" optional=true
+          arg "LAST"
+        }
+      }
+    "#;
+    let lints = lint_str(text, InputFormat::Kdl).unwrap();
+    let names = lints
+      .iter()
+      .map(|lint| match lint {
+        Lint::MisplacedArg {
+          name,
+          span: Some(_),
+          ..
+        } => name.as_str(),
+        lint => panic!("unexpected lint {lint:?}"),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(vec!["REST", "LAST"], names);
+  }
+
+  #[test]
+  fn test_json() {
+    let text = r#"{
+      "name": "foo",
+      "flags": [
+        {"forms": ["-a"], "desc": "Line 1\nLine 2"},
+        {"forms": ["-b"], "typ": {"Strings": [["it's", null]]}},
+        {"forms": ["-j"], "typ": {"Int": {"min": 4, "max": 1}}}
+      ]
+    }"#;
+    let lints = lint_str(text, InputFormat::Json).unwrap();
+    assert!(matches!(
+      lints.as_slice(),
+      [
+        Lint::Unescapable { span: None, .. },
+        Lint::Unescapable { span: None, .. },
+        Lint::EmptyRange {
+          min: 4,
+          max: 1,
+          span: None
+        },
+      ]
+    ));
+  }
+}
@@ -1,67 +1,379 @@
-//! For parsing completions from a serialization language (KDL or JSON)
+//! For parsing completions from a serialization language (KDL, JSON, TOML, or
+//! YAML)
 
 pub mod error;
 mod kdl;
+pub mod lint;
+pub mod overlay;
+mod readable;
+pub mod schema;
 
-use std::{fs, path::Path};
+use std::{
+  fs,
+  io::{self, Read},
+  path::Path,
+};
 
-use miette::NamedSource;
+use ::kdl::KdlDocument;
+use clap::ValueEnum;
+use log::debug;
+use miette::{NamedSource, Report};
 
-use self::error::DeserError;
+use self::{error::DeserError, overlay::Overlay};
 use crate::{parse_deser::error::Error, CommandInfo};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
 pub enum InputFormat {
   Kdl,
   Json,
+  Toml,
+  Yaml,
 }
 
+impl InputFormat {
+  /// Figure out the format from a file extension
+  #[must_use]
+  pub fn from_ext(ext: &str) -> Option<Self> {
+    match ext {
+      "json" => Some(InputFormat::Json),
+      "kdl" => Some(InputFormat::Kdl),
+      "toml" => Some(InputFormat::Toml),
+      "yaml" | "yml" => Some(InputFormat::Yaml),
+      _ => None,
+    }
+  }
+
+  /// Guess the format by checking which format the text is valid in. YAML is
+  /// a superset of JSON, so JSON is tried first, and YAML is only picked if
+  /// the text is a mapping (otherwise most plain text would count as YAML).
+  #[must_use]
+  pub fn sniff(text: &str) -> Option<Self> {
+    if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+      Some(InputFormat::Json)
+    } else if toml::from_str::<toml::Table>(text).is_ok() {
+      Some(InputFormat::Toml)
+    } else if text.parse::<KdlDocument>().is_ok() {
+      Some(InputFormat::Kdl)
+    } else if serde_yaml::from_str::<serde_yaml::Value>(text)
+      .is_ok_and(|value| value.is_mapping())
+    {
+      Some(InputFormat::Yaml)
+    } else {
+      None
+    }
+  }
+}
+
+/// Parse a file, figuring out its format from its extension, or from its
+/// contents if the extension isn't recognized
+///
 /// # Errors
 ///
-/// Fails if the file's extension isn't recognized (only KDL and JSON are
-/// supported), or if [`parse_from_str`] fails.
+/// Fails if the format couldn't be figured out, or if [`parse_from_str`] fails.
 pub fn parse(file: impl AsRef<Path>) -> Result<CommandInfo> {
+  parse_file(file.as_ref(), None, parse_from_str_in)
+}
+
+/// Parse all the commands in a file (see [`parse_all_from_str`]). If `format`
+/// is given, the file's extension and contents are ignored.
+///
+/// # Errors
+///
+/// Fails if the format couldn't be figured out, or if [`parse_all_from_str`]
+/// fails.
+pub fn parse_all(
+  file: impl AsRef<Path>,
+  format: Option<InputFormat>,
+) -> Result<Vec<CommandInfo>> {
+  parse_file(file.as_ref(), format, parse_all_from_str_in)
+}
+
+/// Parse all the commands in a directory, going through every file with a
+/// known extension (other files are skipped). Files are read in order of
+/// their names.
+///
+/// # Errors
+///
+/// Fails if the directory couldn't be read or [`parse_all`] fails for any of
+/// the files
+pub fn parse_dir(dir: impl AsRef<Path>) -> Result<Vec<CommandInfo>> {
+  let dir = dir.as_ref();
+  let io_error = |source| Error::Io {
+    file_path: dir.to_string_lossy().to_string(),
+    source,
+  };
+  let mut files = fs::read_dir(dir)
+    .map_err(io_error)?
+    .map(|entry| entry.map(|entry| entry.path()))
+    .collect::<io::Result<Vec<_>>>()
+    .map_err(io_error)?;
+  files.sort();
+
+  let mut cmds = Vec::new();
+  for file in files {
+    let format = file
+      .extension()
+      .and_then(|ext| ext.to_str())
+      .and_then(InputFormat::from_ext);
+    if format.is_none() || !file.is_file() {
+      debug!("Skipping {}", file.display());
+      continue;
+    }
+    cmds.extend(parse_all(&file, format)?);
+  }
+  Ok(cmds)
+}
+
+/// Parse all the commands given through stdin. If `format` isn't given, it's
+/// guessed from the text. Included files are looked for relative to the
+/// current directory.
+///
+/// # Errors
+///
+/// Fails if stdin couldn't be read, if the format couldn't be figured out, or
+/// if [`parse_all_from_str`] fails.
+pub fn parse_stdin(format: Option<InputFormat>) -> Result<Vec<CommandInfo>> {
+  let file_path = "<stdin>".to_owned();
+  let mut text = String::new();
+  match io::stdin().read_to_string(&mut text) {
+    Ok(_) => parse_text(
+      file_path,
+      text,
+      format,
+      Path::new(""),
+      parse_all_from_str_in,
+    ),
+    Err(e) => Err(Error::Io {
+      file_path,
+      source: e,
+    }),
+  }
+}
+
+/// Parse an [`Overlay`] from a KDL or JSON file
+///
+/// # Errors
+///
+/// Fails if the format couldn't be figured out, or if
+/// [`parse_overlay_from_str`] fails.
+pub fn parse_overlay(file: impl AsRef<Path>) -> Result<Overlay> {
+  parse_file(file.as_ref(), None, |text, format, _| {
+    parse_overlay_from_str(text, format)
+  })
+}
+
+/// Check a config file for problems that don't stop it from being
+/// deserialized, returning a report for each problem found
+///
+/// # Errors
+///
+/// Fails if the format couldn't be figured out, or if the file couldn't be
+/// deserialized at all.
+pub fn lint(file: impl AsRef<Path>) -> Result<Vec<Report>> {
   let file = file.as_ref();
+  parse_file(file, None, |text, format, dir| {
+    Ok(
+      lint::lint_str_in(text, format, dir)?
+        .into_iter()
+        .map(|lint| {
+          Report::new(lint).with_source_code(NamedSource::new(
+            file.to_string_lossy(),
+            text.to_string(),
+          ))
+        })
+        .collect(),
+    )
+  })
+}
+
+/// Read a file and deserialize it based on its extension (or contents).
+/// `parse_str` is also given the directory the file is in, for resolving
+/// includes.
+fn parse_file<T>(
+  file: &Path,
+  format: Option<InputFormat>,
+  parse_str: impl FnOnce(
+    &str,
+    InputFormat,
+    &Path,
+  ) -> std::result::Result<T, DeserError>,
+) -> Result<T> {
   let file_path = file.to_string_lossy().to_string();
-  if let Some(ext) = file.extension() {
-    match fs::read_to_string(file) {
-      Ok(text) => {
-        if let Some(ext) = ext.to_str() {
-          let format = match ext {
-            "json" => InputFormat::Json,
-            "kdl" => InputFormat::Kdl,
-            _ => return Err(Error::UnrecognizableExtension { file_path }),
-          };
-          parse_from_str(&text, format).map_err(|error| Error::Deser {
-            source_code: NamedSource::new(file_path, text),
-            error: Box::new(error),
-          })
-        } else {
-          Err(Error::UnrecognizableExtension { file_path })
-        }
-      }
-      Err(e) => Err(Error::Io {
-        file_path,
-        source: e,
-      }),
+  match fs::read_to_string(file) {
+    Ok(text) => {
+      let format = format.or_else(|| {
+        file
+          .extension()
+          .and_then(|ext| ext.to_str())
+          .and_then(InputFormat::from_ext)
+      });
+      let dir = file.parent().unwrap_or(Path::new(""));
+      parse_text(file_path, text, format, dir, parse_str)
     }
-  } else {
-    Err(Error::NoExtension { file_path })
+    Err(e) => Err(Error::Io {
+      file_path,
+      source: e,
+    }),
   }
 }
 
+/// Deserialize some text, guessing its format if it isn't given
+fn parse_text<T>(
+  file_path: String,
+  text: String,
+  format: Option<InputFormat>,
+  dir: &Path,
+  parse_str: impl FnOnce(
+    &str,
+    InputFormat,
+    &Path,
+  ) -> std::result::Result<T, DeserError>,
+) -> Result<T> {
+  let Some(format) = format.or_else(|| InputFormat::sniff(&text)) else {
+    return Err(Error::UnknownFormat { file_path });
+  };
+  parse_str(&text, format, dir).map_err(|error| {
+    // Errors in included files should be shown with that file's contents
+    let source_code = match &error {
+      DeserError::Kdl(error) => error.included_file(),
+      _ => None,
+    }
+    .map_or_else(
+      || NamedSource::new(&file_path, text.clone()),
+      |(path, text)| NamedSource::new(path, text.to_string()),
+    );
+    Error::Deser {
+      source_code,
+      error: Box::new(error),
+    }
+  })
+}
+
+/// TOML and YAML use the same shape as KDL (see the `readable` module's docs),
+/// while JSON uses serde's encoding of [`CommandInfo`]. Files included from
+/// KDL are looked for relative to the current directory.
+///
 /// # Errors
 ///
-/// Fails if the shape of the KDL/JSON didn't match a [`CommandInfo`]
+/// Fails if the shape of the text didn't match a [`CommandInfo`]
 pub fn parse_from_str(
   text: &str,
   format: InputFormat,
+) -> std::result::Result<CommandInfo, DeserError> {
+  parse_from_str_in(text, format, Path::new(""))
+}
+
+/// Like [`parse_from_str`], but KDL includes are resolved relative to `dir`
+fn parse_from_str_in(
+  text: &str,
+  format: InputFormat,
+  dir: &Path,
 ) -> std::result::Result<CommandInfo, DeserError> {
   let cmd_info = match format {
-    InputFormat::Json => serde_json::from_str(text)?,
-    InputFormat::Kdl => kdl::parse_from_str(text)?,
+    InputFormat::Json => schema::parse_json(text)?,
+    InputFormat::Kdl => kdl::parse_from_str(text, dir)?,
+    InputFormat::Toml => readable::parse_toml(text)?,
+    InputFormat::Yaml => readable::parse_yaml(text)?,
   };
   Ok(cmd_info)
 }
+
+/// Deserialize text that may have multiple commands in it. For KDL, each
+/// top-level node is a command. For JSON and YAML, the text can be either a
+/// single command or an array of commands. TOML can only have one command.
+///
+/// # Errors
+///
+/// Fails if the shape of the text didn't match a [`CommandInfo`] or a list of
+/// them
+pub fn parse_all_from_str(
+  text: &str,
+  format: InputFormat,
+) -> std::result::Result<Vec<CommandInfo>, DeserError> {
+  parse_all_from_str_in(text, format, Path::new(""))
+}
+
+/// Like [`parse_all_from_str`], but KDL includes are resolved relative to
+/// `dir`
+fn parse_all_from_str_in(
+  text: &str,
+  format: InputFormat,
+  dir: &Path,
+) -> std::result::Result<Vec<CommandInfo>, DeserError> {
+  let cmds = match format {
+    InputFormat::Json => schema::parse_json_all(text)?,
+    InputFormat::Kdl => kdl::parse_all_from_str(text, dir)?,
+    InputFormat::Toml => vec![readable::parse_toml(text)?],
+    InputFormat::Yaml => readable::parse_yaml_all(text)?,
+  };
+  Ok(cmds)
+}
+
+/// # Errors
+///
+/// Fails if the shape of the KDL/JSON didn't match an [`Overlay`]. Overlays
+/// can't be written in TOML or YAML.
+pub fn parse_overlay_from_str(
+  text: &str,
+  format: InputFormat,
+) -> std::result::Result<Overlay, DeserError> {
+  let overlay = match format {
+    InputFormat::Json => serde_json::from_str(text)?,
+    InputFormat::Kdl => kdl::parse_overlay_from_str(text)?,
+    InputFormat::Toml | InputFormat::Yaml => {
+      return Err(DeserError::UnsupportedFormat {
+        what: "Overlays".to_owned(),
+        format: format!("{format:?}"),
+      })
+    }
+  };
+  Ok(overlay)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{parse_from_str, InputFormat};
+  use crate::{Arg, ArgType};
+
+  #[test]
+  fn sniff() {
+    let cases = [
+      (r#"{"name": "foo"}"#, Some(InputFormat::Json)),
+      (
+        "name = \"foo\"\n[[flags]]\nforms = [\"-a\"]",
+        Some(InputFormat::Toml),
+      ),
+      ("foo {\n  flags {\n    -a\n  }\n}", Some(InputFormat::Kdl)),
+      (
+        "name: foo\nflags:\n  - forms: [-a]",
+        Some(InputFormat::Yaml),
+      ),
+      ("just some text", None),
+    ];
+    for (text, expected) in cases {
+      assert_eq!(expected, InputFormat::sniff(text), "sniffing {text:?}");
+    }
+  }
+
+  #[test]
+  fn bare_arg_types() {
+    let cmd = parse_from_str(
+      r#"{"name": "foo", "args": ["Path", {"Strings": [["a", null]]}, {"name": "DIR", "typ": "Dir"}]}"#,
+      InputFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(
+      vec![
+        ArgType::Path.into(),
+        ArgType::Strings(vec![("a".to_owned(), None)]).into(),
+        Arg {
+          name: Some("DIR".to_owned()),
+          ..ArgType::Dir.into()
+        },
+      ],
+      cmd.args
+    );
+  }
+}
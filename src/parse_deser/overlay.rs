@@ -0,0 +1,287 @@
+//! Patches to apply on top of command information, e.g. to fix up completions
+//! generated from man pages without having to maintain a whole config file
+//!
+//! An overlay has the same shape as a [`CommandInfo`], but everything is
+//! optional. Flags are matched by their forms and subcommands are matched by
+//! their names. Anything not mentioned in the overlay is left alone.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  merge::{find_flag, union},
+  Arg, ArgType, CommandInfo, Flag, FlagArg,
+};
+
+/// A patch for a single command and its subcommands
+#[derive(Debug, Default, Deserialize, Eq, Serialize, PartialEq)]
+pub struct Overlay {
+  /// The name of the command to patch
+  pub name: String,
+  /// A new name for the command
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub rename: Option<String>,
+  /// A new description for the command
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub desc: Option<String>,
+  /// Flags to add, or to merge into existing flags with the same forms
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub flags: Vec<FlagPatch>,
+  /// Forms of flags to remove. A flag is removed entirely if any of its
+  /// forms are given here.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub remove_flags: Vec<String>,
+  /// If given, replaces the command's arguments
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub args: Option<Vec<Arg>>,
+  /// Subcommands to add or patch
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub subcommands: Vec<Overlay>,
+  /// Names of subcommands to remove
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub remove_subcommands: Vec<String>,
+  /// If given, replaces the command's aliases
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub aliases: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hidden: Option<bool>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub deprecated: Option<bool>,
+}
+
+/// A patch for a single flag
+#[derive(Debug, Default, Deserialize, Eq, Serialize, PartialEq)]
+pub struct FlagPatch {
+  /// The forms of the flag. If an existing flag has any of these forms, it's
+  /// patched, otherwise a new flag is added.
+  pub forms: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub desc: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub typ: Option<ArgType>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub arg: Option<FlagArg>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub requires: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub conflicts: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub repeatable: Option<bool>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub persistent: Option<bool>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub aliases: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hidden: Option<bool>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub deprecated: Option<bool>,
+  /// Replace the existing flag entirely instead of merging into it
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub replace: bool,
+}
+
+impl Overlay {
+  /// Deep-merge this overlay onto a command
+  pub fn apply(&self, cmd: &mut CommandInfo) {
+    if let Some(name) = &self.rename {
+      cmd.name.clone_from(name);
+    }
+    if self.desc.is_some() {
+      cmd.desc.clone_from(&self.desc);
+    }
+    if let Some(aliases) = &self.aliases {
+      cmd.aliases.clone_from(aliases);
+    }
+    if let Some(hidden) = self.hidden {
+      cmd.hidden = hidden;
+    }
+    if let Some(deprecated) = self.deprecated {
+      cmd.deprecated = deprecated;
+    }
+
+    cmd.flags.retain(|flag| {
+      !flag
+        .forms
+        .iter()
+        .any(|form| self.remove_flags.contains(form))
+    });
+    for patch in &self.flags {
+      match find_flag(&mut cmd.flags, &patch.forms) {
+        Some(flag) => patch.apply(flag),
+        None => cmd.flags.push(patch.to_flag()),
+      }
+    }
+
+    if let Some(args) = &self.args {
+      cmd.args.clone_from(args);
+    }
+
+    for name in &self.remove_subcommands {
+      if !cmd.subcommands.iter().any(|sub| &sub.name == name) {
+        warn!(
+          "Overlay for {} removes subcommand {name}, which doesn't exist",
+          self.name
+        );
+      }
+    }
+    cmd
+      .subcommands
+      .retain(|sub| !self.remove_subcommands.contains(&sub.name));
+    for sub_overlay in &self.subcommands {
+      let existing = cmd
+        .subcommands
+        .iter_mut()
+        .find(|sub| sub.name == sub_overlay.name);
+      match existing {
+        Some(sub) => sub_overlay.apply(sub),
+        None => cmd.subcommands.push(sub_overlay.to_cmd_info()),
+      }
+    }
+  }
+
+  /// Make a new command out of this overlay, for subcommands that didn't
+  /// exist before
+  fn to_cmd_info(&self) -> CommandInfo {
+    let mut cmd = CommandInfo {
+      name: self.name.clone(),
+      ..Default::default()
+    };
+    self.apply(&mut cmd);
+    cmd
+  }
+}
+
+impl FlagPatch {
+  fn apply(&self, flag: &mut Flag) {
+    if self.replace {
+      *flag = self.to_flag();
+    } else {
+      self.patch(flag);
+    }
+  }
+
+  /// Set everything given in this patch on the flag, ignoring `replace`
+  fn patch(&self, flag: &mut Flag) {
+    flag.forms = union(&flag.forms, &self.forms);
+    if self.desc.is_some() {
+      flag.desc.clone_from(&self.desc);
+    }
+    if self.typ.is_some() {
+      flag.typ.clone_from(&self.typ);
+    }
+    if self.arg.is_some() {
+      flag.arg.clone_from(&self.arg);
+    }
+    if let Some(requires) = &self.requires {
+      flag.requires.clone_from(requires);
+    }
+    if let Some(conflicts) = &self.conflicts {
+      flag.conflicts.clone_from(conflicts);
+    }
+    if let Some(repeatable) = self.repeatable {
+      flag.repeatable = repeatable;
+    }
+    if let Some(persistent) = self.persistent {
+      flag.persistent = persistent;
+    }
+    if let Some(aliases) = &self.aliases {
+      flag.aliases.clone_from(aliases);
+    }
+    if let Some(hidden) = self.hidden {
+      flag.hidden = hidden;
+    }
+    if let Some(deprecated) = self.deprecated {
+      flag.deprecated = deprecated;
+    }
+  }
+
+  /// Make a new flag out of this patch, for flags that didn't exist before
+  fn to_flag(&self) -> Flag {
+    let mut flag = Flag::default();
+    self.patch(&mut flag);
+    flag
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::{FlagPatch, Overlay};
+  use crate::{
+    test_util::{cmd, flag},
+    ArgType,
+  };
+
+  #[test]
+  fn test_apply() {
+    let mut git = cmd(
+      "git",
+      vec![
+        flag(&["-v", "--version"], Some("Version")),
+        flag(&["--bogus"], None),
+      ],
+      vec![
+        cmd("checkout", vec![flag(&["-b"], Some("New branch"))], vec![]),
+        cmd("remove-me", vec![], vec![]),
+        cmd("old", vec![], vec![]),
+      ],
+    );
+
+    let overlay = Overlay {
+      name: "git".to_owned(),
+      remove_flags: vec!["--bogus".to_owned()],
+      flags: vec![FlagPatch {
+        forms: vec!["--version".to_owned(), "-V".to_owned()],
+        desc: Some("Show the version".to_owned()),
+        ..Default::default()
+      }],
+      subcommands: vec![
+        Overlay {
+          name: "checkout".to_owned(),
+          args: Some(vec![ArgType::Run {
+            cmd: "git branch".to_owned(),
+            sep: None,
+          }
+          .into()]),
+          flags: vec![FlagPatch {
+            forms: vec!["-b".to_owned()],
+            replace: true,
+            ..Default::default()
+          }],
+          ..Default::default()
+        },
+        Overlay {
+          name: "old".to_owned(),
+          rename: Some("new".to_owned()),
+          ..Default::default()
+        },
+        Overlay {
+          name: "added".to_owned(),
+          desc: Some("Not in the man page".to_owned()),
+          ..Default::default()
+        },
+      ],
+      remove_subcommands: vec!["remove-me".to_owned()],
+      ..Default::default()
+    };
+    overlay.apply(&mut git);
+
+    let mut expected = cmd(
+      "git",
+      vec![flag(&["-v", "--version", "-V"], Some("Show the version"))],
+      vec![
+        cmd("checkout", vec![flag(&["-b"], None)], vec![]),
+        cmd("new", vec![], vec![]),
+        cmd("added", vec![], vec![]),
+      ],
+    );
+    expected.subcommands[0].args = vec![ArgType::Run {
+      cmd: "git branch".to_owned(),
+      sep: None,
+    }
+    .into()];
+    expected.subcommands[2].desc = Some("Not in the man page".to_owned());
+    assert_eq!(expected, git);
+  }
+}
@@ -0,0 +1,799 @@
+//! For deserializing from TOML and YAML
+//!
+//! These formats use the same shape as the KDL format rather than serde's
+//! encoding of [`CommandInfo`], since TOML doesn't have `null` and serde's
+//! encoding of [`ArgType`] (e.g. `{"Run": {"cmd": "ls", "sep": null}}`) isn't
+//! very readable. A command looks like this in TOML:
+//!
+//! ```toml
+//! name = "foo"
+//! desc = "Do foo things"
+//! args = ["path", { name = "REST", type = "dir", variadic = true }]
+//!
+//! [[flags]]
+//! forms = ["-o", "--output"]
+//! desc = "Where to write to"
+//! type = ["path", { run = "ls /tmp" }]
+//!
+//! [[flags]]
+//! forms = ["--color"]
+//! type = { strings = ["always", { value = "never", desc = "No color" }] }
+//!
+//! [[subcommands]]
+//! name = "bar"
+//! ```
+//!
+//! Types are written as follows:
+//! - `"path"`, `"dir"`, `"command"`, and `"unknown"` for the simple types
+//! - `{ run = "cmd" }` or `{ run = { cmd = "cmd", sep = "\t" } }`
+//! - `{ strings = [...] }`, where each string is either just the value or `{
+//!   value = "...", desc = "..." }`
+//! - `{ any = [...] }`, or just a list of types, for any of the given types
+//!
+//! A YAML file can also be a list of commands. TOML files can only have one
+//! command, since the top level of a TOML file has to be a table.
+
+use std::fmt;
+
+use serde::{
+  de::{self, MapAccess, SeqAccess, Visitor},
+  Deserialize, Deserializer,
+};
+
+use super::error::DeserError;
+use crate::{Arg, ArgStyle, ArgType, CommandInfo, Flag, FlagArg, ValueCount};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Command {
+  name: String,
+  #[serde(default)]
+  desc: Option<String>,
+  #[serde(default)]
+  flags: Vec<FlagShape>,
+  #[serde(default)]
+  args: Vec<Positional>,
+  #[serde(default)]
+  subcommands: Vec<Command>,
+  #[serde(default)]
+  exclusive: Vec<Vec<String>>,
+  #[serde(default)]
+  aliases: Vec<String>,
+  #[serde(default)]
+  hidden: bool,
+  #[serde(default)]
+  deprecated: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlagShape {
+  forms: Vec<String>,
+  #[serde(default)]
+  desc: Option<String>,
+  #[serde(default, rename = "type")]
+  typ: Option<Type>,
+  #[serde(default)]
+  arg: Option<ArgShape>,
+  #[serde(default)]
+  requires: Vec<String>,
+  #[serde(default)]
+  conflicts: Vec<String>,
+  #[serde(default)]
+  repeatable: bool,
+  #[serde(default)]
+  persistent: bool,
+  #[serde(default)]
+  aliases: Vec<String>,
+  #[serde(default)]
+  hidden: bool,
+  #[serde(default)]
+  deprecated: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArgShape {
+  #[serde(default)]
+  name: Option<String>,
+  #[serde(default)]
+  count: Option<Count>,
+  #[serde(default)]
+  style: Option<Style>,
+}
+
+/// A positional argument, or just its type for an unnamed, required argument.
+/// Tables are taken to be full arguments if their first key is one of
+/// [`POSITIONAL_FIELDS`], and types otherwise.
+enum Positional {
+  Type(Type),
+  Full(PositionalShape),
+}
+
+#[derive(Default)]
+struct PositionalShape {
+  name: Option<String>,
+  desc: Option<String>,
+  typ: Option<Type>,
+  optional: bool,
+  variadic: bool,
+}
+
+const POSITIONAL_FIELDS: &[&str] =
+  &["name", "desc", "type", "optional", "variadic"];
+
+/// A [`ValueCount`] written as `"one"`, `"optional"`, a number, etc.
+struct Count(ValueCount);
+
+/// An [`ArgStyle`] written as `"any"`, `"attached"`, or `"separate"`
+struct Style(ArgStyle);
+
+/// An [`ArgType`] written the same way as in KDL
+struct Type(ArgType);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringValue {
+  Plain(String),
+  Described(DescribedString),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DescribedString {
+  value: String,
+  #[serde(default)]
+  desc: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GlobPaths {
+  Plain(Vec<String>),
+  Full(GlobPathsShape),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GlobPathsShape {
+  globs: Vec<String>,
+  #[serde(default = "default_dirs")]
+  dirs: bool,
+}
+
+fn default_dirs() -> bool {
+  true
+}
+
+/// The type of a list's values, and optionally the separator. Just the type is
+/// tried first, otherwise serde would read a list of types as the fields of
+/// `Full`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListType {
+  Plain(Type),
+  Full(ListShape),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListShape {
+  #[serde(rename = "type")]
+  typ: Type,
+  #[serde(default)]
+  sep: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntRange {
+  #[serde(default)]
+  min: Option<i64>,
+  #[serde(default)]
+  max: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RunCmd {
+  Plain(String),
+  Full(RunShape),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunShape {
+  cmd: String,
+  #[serde(default)]
+  sep: Option<String>,
+}
+
+/// Parse a TOML string as a [`CommandInfo`]
+///
+/// # Errors
+///
+/// Fails if the TOML is invalid or doesn't match the shape of a command
+pub fn parse_toml(text: &str) -> Result<CommandInfo, DeserError> {
+  let cmd: Command =
+    toml::from_str(text).map_err(|error| DeserError::Toml {
+      message: error.message().to_string(),
+      span: error.span().map(|span| (span.start, span.len()).into()),
+    })?;
+  Ok(cmd.into())
+}
+
+/// Parse a YAML string as a [`CommandInfo`]
+///
+/// # Errors
+///
+/// Fails if the YAML is invalid or doesn't match the shape of a command
+pub fn parse_yaml(text: &str) -> Result<CommandInfo, DeserError> {
+  let cmd: Command = serde_yaml::from_str(text).map_err(yaml_error)?;
+  Ok(cmd.into())
+}
+
+/// Parse a YAML string as either a single [`CommandInfo`] or a list of them
+///
+/// # Errors
+///
+/// Fails if the YAML is invalid or doesn't match the shape of a command or a
+/// list of commands
+pub fn parse_yaml_all(text: &str) -> Result<Vec<CommandInfo>, DeserError> {
+  let value: serde_yaml::Value =
+    serde_yaml::from_str(text).map_err(yaml_error)?;
+  if value.is_sequence() {
+    // Deserialize from the text again rather than from the value so that
+    // errors have locations
+    let cmds: Vec<Command> = serde_yaml::from_str(text).map_err(yaml_error)?;
+    Ok(cmds.into_iter().map(CommandInfo::from).collect())
+  } else {
+    Ok(vec![parse_yaml(text)?])
+  }
+}
+
+fn yaml_error(error: serde_yaml::Error) -> DeserError {
+  DeserError::Yaml {
+    span: error.location().map(|loc| loc.index().into()),
+    message: error.to_string(),
+  }
+}
+
+impl From<Command> for CommandInfo {
+  fn from(cmd: Command) -> Self {
+    CommandInfo {
+      name: cmd.name,
+      desc: cmd.desc,
+      flags: cmd
+        .flags
+        .into_iter()
+        .map(|flag| Flag {
+          forms: flag.forms,
+          desc: flag.desc,
+          typ: flag.typ.map(|typ| typ.0),
+          arg: flag.arg.map(|arg| FlagArg {
+            count: arg.count.map_or(ValueCount::One, |count| count.0),
+            name: arg.name,
+            style: arg.style.map_or(ArgStyle::Any, |style| style.0),
+          }),
+          requires: flag.requires,
+          conflicts: flag.conflicts,
+          repeatable: flag.repeatable,
+          persistent: flag.persistent,
+          aliases: flag.aliases,
+          hidden: flag.hidden,
+          deprecated: flag.deprecated,
+        })
+        .collect(),
+      args: cmd
+        .args
+        .into_iter()
+        .map(|arg| match arg {
+          Positional::Full(arg) => Arg {
+            name: arg.name,
+            desc: arg.desc,
+            typ: arg.typ.map(|typ| typ.0),
+            optional: arg.optional,
+            variadic: arg.variadic,
+          },
+          Positional::Type(typ) => Arg::from(typ.0),
+        })
+        .collect(),
+      subcommands: cmd.subcommands.into_iter().map(CommandInfo::from).collect(),
+      exclusive: cmd.exclusive,
+      aliases: cmd.aliases,
+      hidden: cmd.hidden,
+      deprecated: cmd.deprecated,
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for Type {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    deserializer.deserialize_any(TypeVisitor)
+  }
+}
+
+struct TypeVisitor;
+
+impl<'de> Visitor<'de> for TypeVisitor {
+  type Value = Type;
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      r#"a type like "path", "dir", "command", "user", "unknown", {{ path = [...] }}, {{ run = ... }}, {{ strings = [...] }}, or a list of types"#
+    )
+  }
+
+  fn visit_str<E: de::Error>(self, typ: &str) -> Result<Type, E> {
+    let typ = match typ {
+      "path" => ArgType::Path,
+      "dir" => ArgType::Dir,
+      "command" => ArgType::CommandName,
+      "unknown" => ArgType::Unknown,
+      "user" => ArgType::User,
+      "group" => ArgType::Group,
+      "host" => ArgType::Host,
+      "pid" => ArgType::Pid,
+      "signal" => ArgType::Signal,
+      "env-var" => ArgType::EnvVar,
+      "interface" => ArgType::NetInterface,
+      "port" => ArgType::Port,
+      "int" => ArgType::Int {
+        min: None,
+        max: None,
+      },
+      "float" => ArgType::Float,
+      "duration" => ArgType::Duration,
+      "url" => ArgType::Url,
+      "string" => ArgType::Text,
+      typ => {
+        return Err(E::unknown_variant(
+          typ,
+          &[
+            "path",
+            "dir",
+            "command",
+            "unknown",
+            "user",
+            "group",
+            "host",
+            "pid",
+            "signal",
+            "env-var",
+            "interface",
+            "port",
+            "int",
+            "float",
+            "duration",
+            "url",
+            "string",
+          ],
+        ))
+      }
+    };
+    Ok(Type(typ))
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Type, A::Error> {
+    let mut types = Vec::new();
+    while let Some(Type(typ)) = seq.next_element()? {
+      types.push(typ);
+    }
+    Ok(Type(ArgType::Any(types)))
+  }
+
+  fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Type, A::Error> {
+    let Some(key) = map.next_key::<String>()? else {
+      return Err(de::Error::invalid_length(0, &self));
+    };
+    type_from_map(&key, map)
+  }
+}
+
+/// Finish reading a type written as a table, whose only key has already been
+/// read
+fn type_from_map<'de, A: MapAccess<'de>>(
+  key: &str,
+  mut map: A,
+) -> Result<Type, A::Error> {
+  let typ = match key {
+    "run" => match map.next_value()? {
+      RunCmd::Plain(cmd) => ArgType::Run { cmd, sep: None },
+      RunCmd::Full(RunShape { cmd, sep }) => ArgType::Run { cmd, sep },
+    },
+    "path" => {
+      let (globs, dirs) = match map.next_value()? {
+        GlobPaths::Plain(globs) => (globs, true),
+        GlobPaths::Full(GlobPathsShape { globs, dirs }) => (globs, dirs),
+      };
+      if globs.is_empty() {
+        ArgType::Path
+      } else {
+        ArgType::Glob { globs, dirs }
+      }
+    }
+    "list" => {
+      let (Type(typ), sep) = match map.next_value()? {
+        ListType::Plain(typ) => (typ, None),
+        ListType::Full(ListShape { typ, sep }) => (typ, sep),
+      };
+      ArgType::List {
+        typ: Box::new(typ),
+        sep: sep.unwrap_or_else(|| ",".to_owned()),
+      }
+    }
+    "int" => {
+      let IntRange { min, max } = map.next_value()?;
+      ArgType::Int { min, max }
+    }
+    "strings" => ArgType::Strings(
+      map
+        .next_value::<Vec<StringValue>>()?
+        .into_iter()
+        .map(|value| match value {
+          StringValue::Plain(value) => (value, None),
+          StringValue::Described(DescribedString { value, desc }) => {
+            (value, desc)
+          }
+        })
+        .collect(),
+    ),
+    "any" => {
+      let Type(typ) = map.next_value_seed(AnySeed)?;
+      typ
+    }
+    key => {
+      return Err(de::Error::unknown_variant(
+        key,
+        &["path", "int", "list", "run", "strings", "any"],
+      ))
+    }
+  };
+  if let Some(key) = map.next_key::<String>()? {
+    return Err(de::Error::custom(format!(
+      "unexpected key {key}, a type should only have one key"
+    )));
+  }
+  Ok(Type(typ))
+}
+
+impl<'de> Deserialize<'de> for Positional {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    deserializer.deserialize_any(PositionalVisitor)
+  }
+}
+
+struct PositionalVisitor;
+
+impl<'de> Visitor<'de> for PositionalVisitor {
+  type Value = Positional;
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "a type, or a table with the argument's name, desc, type, etc."
+    )
+  }
+
+  fn visit_str<E: de::Error>(self, typ: &str) -> Result<Positional, E> {
+    TypeVisitor.visit_str(typ).map(Positional::Type)
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(
+    self,
+    seq: A,
+  ) -> Result<Positional, A::Error> {
+    TypeVisitor.visit_seq(seq).map(Positional::Type)
+  }
+
+  fn visit_map<A: MapAccess<'de>>(
+    self,
+    mut map: A,
+  ) -> Result<Positional, A::Error> {
+    let mut arg = PositionalShape::default();
+    let mut key = map.next_key::<String>()?;
+    if let Some(key) = key
+      .as_deref()
+      .filter(|key| !POSITIONAL_FIELDS.contains(key))
+    {
+      return type_from_map(key, map).map(Positional::Type);
+    }
+    while let Some(field) = key {
+      match field.as_str() {
+        "name" => arg.name = Some(map.next_value()?),
+        "desc" => arg.desc = Some(map.next_value()?),
+        "type" => arg.typ = Some(map.next_value()?),
+        "optional" => arg.optional = map.next_value()?,
+        "variadic" => arg.variadic = map.next_value()?,
+        field => {
+          return Err(de::Error::unknown_field(field, POSITIONAL_FIELDS))
+        }
+      }
+      key = map.next_key()?;
+    }
+    Ok(Positional::Full(arg))
+  }
+}
+
+impl<'de> Deserialize<'de> for Count {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    struct CountVisitor;
+
+    impl<'de> Visitor<'de> for CountVisitor {
+      type Value = Count;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "zero, one, optional, variadic, or a number")
+      }
+
+      fn visit_u64<E: de::Error>(self, count: u64) -> Result<Count, E> {
+        usize::try_from(count)
+          .map(|count| Count(ValueCount::Exactly(count)))
+          .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(count), &self))
+      }
+
+      fn visit_i64<E: de::Error>(self, count: i64) -> Result<Count, E> {
+        usize::try_from(count)
+          .map(|count| Count(ValueCount::Exactly(count)))
+          .map_err(|_| E::invalid_value(de::Unexpected::Signed(count), &self))
+      }
+
+      fn visit_str<E: de::Error>(self, count: &str) -> Result<Count, E> {
+        count.parse().map(Count).map_err(E::custom)
+      }
+    }
+
+    deserializer.deserialize_any(CountVisitor)
+  }
+}
+
+impl<'de> Deserialize<'de> for Style {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?
+      .parse()
+      .map(Style)
+      .map_err(de::Error::custom)
+  }
+}
+
+/// For `{ any = [...] }`, which has to be a list
+struct AnySeed;
+
+impl<'de> de::DeserializeSeed<'de> for AnySeed {
+  type Value = Type;
+
+  fn deserialize<D: Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Self::Value, D::Error> {
+    deserializer.deserialize_seq(TypeVisitor)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::{parse_toml, parse_yaml, parse_yaml_all};
+  use crate::{
+    parse_deser::error::DeserError, ArgStyle, ArgType, CommandInfo, Flag,
+    FlagArg, ValueCount,
+  };
+
+  fn expected() -> CommandInfo {
+    CommandInfo {
+      name: "foo".to_owned(),
+      desc: Some("Do foo things".to_owned()),
+      flags: vec![
+        Flag {
+          forms: vec!["-o".to_owned(), "--output".to_owned()],
+          desc: Some("Where to write to".to_owned()),
+          typ: Some(ArgType::Any(vec![
+            ArgType::Path,
+            ArgType::Run {
+              cmd: "ls /tmp".to_owned(),
+              sep: None,
+            },
+          ])),
+          arg: Some(FlagArg {
+            count: ValueCount::Exactly(2),
+            name: None,
+            style: ArgStyle::Any,
+          }),
+          ..Default::default()
+        },
+        Flag {
+          forms: vec!["--color".to_owned()],
+          typ: Some(ArgType::Strings(vec![
+            ("always".to_owned(), None),
+            ("never".to_owned(), Some("No color".to_owned())),
+          ])),
+          arg: Some(FlagArg {
+            count: ValueCount::Optional,
+            name: Some("WHEN".to_owned()),
+            style: ArgStyle::Attached,
+          }),
+          ..Default::default()
+        },
+      ],
+      args: vec![ArgType::Dir.into()],
+      subcommands: vec![CommandInfo {
+        name: "bar".to_owned(),
+        ..Default::default()
+      }],
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_toml() {
+    let text = r#"
+      name = "foo"
+      desc = "Do foo things"
+      args = ["dir"]
+
+      [[flags]]
+      forms = ["-o", "--output"]
+      desc = "Where to write to"
+      type = ["path", { run = "ls /tmp" }]
+      arg = { count = 2 }
+
+      [[flags]]
+      forms = ["--color"]
+      type = { strings = ["always", { value = "never", desc = "No color" }] }
+      arg = { name = "WHEN", count = "optional", style = "attached" }
+
+      [[subcommands]]
+      name = "bar"
+    "#;
+    assert_eq!(expected(), parse_toml(text).unwrap());
+  }
+
+  #[test]
+  fn test_yaml() {
+    let text = r#"
+      name: foo
+      desc: Do foo things
+      args: [dir]
+      flags:
+        - forms: [-o, --output]
+          desc: Where to write to
+          type:
+            any:
+              - path
+              - run: ls /tmp
+          arg:
+            count: 2
+        - forms: [--color]
+          arg: { name: WHEN, count: optional, style: attached }
+          type:
+            strings:
+              - always
+              - value: never
+                desc: No color
+      subcommands:
+        - name: bar
+    "#;
+    assert_eq!(expected(), parse_yaml(text).unwrap());
+  }
+
+  #[test]
+  fn test_yaml_list() {
+    let cmds = parse_yaml_all("- name: foo\n- name: bar\n").unwrap();
+    assert_eq!(
+      vec!["foo", "bar"],
+      cmds.iter().map(|cmd| cmd.name.as_str()).collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn builtin_types() {
+    let text = r#"
+      name = "kill"
+      args = [["pid", "signal"]]
+
+      [[flags]]
+      forms = ["-u"]
+      type = "user"
+
+      [[flags]]
+      forms = ["-n"]
+      type = { int = { min = 1 } }
+
+      [[flags]]
+      forms = ["--to"]
+      type = { list = ["user", "group"] }
+
+      [[flags]]
+      forms = ["--path"]
+      type = { list = { type = "dir", sep = ":" } }
+    "#;
+    let cmd = parse_toml(text).unwrap();
+    assert_eq!(Some(ArgType::User), cmd.flags[0].typ);
+    assert_eq!(
+      Some(ArgType::Int {
+        min: Some(1),
+        max: None
+      }),
+      cmd.flags[1].typ
+    );
+    assert_eq!(
+      Some(ArgType::List {
+        typ: Box::new(ArgType::Any(vec![ArgType::User, ArgType::Group])),
+        sep: ",".to_owned(),
+      }),
+      cmd.flags[2].typ
+    );
+    assert_eq!(
+      Some(ArgType::List {
+        typ: Box::new(ArgType::Dir),
+        sep: ":".to_owned(),
+      }),
+      cmd.flags[3].typ
+    );
+    assert_eq!(
+      Some(ArgType::Any(vec![ArgType::Pid, ArgType::Signal])),
+      cmd.args[0].typ
+    );
+  }
+
+  #[test]
+  fn positional_error_span() {
+    let text = "name = \"foo\"\nargs = [{ name = \"X\", type = \"paths\" }]\n";
+    let Err(DeserError::Toml {
+      message,
+      span: Some(span),
+    }) = parse_toml(text)
+    else {
+      panic!("Should've failed");
+    };
+    assert!(message.contains("paths"), "{message}");
+    assert_eq!(
+      r#""paths""#,
+      &text[span.offset()..span.offset() + span.len()]
+    );
+  }
+
+  #[test]
+  fn unknown_fields() {
+    for typ in [
+      r#"{ list = { type = "dir", sepp = ":" } }"#,
+      r#"{ run = { cmd = "ls", sepp = ":" } }"#,
+      r#"{ path = { globs = ["*.rs"], dir = false } }"#,
+      r#"{ strings = [{ value = "a", description = "b" }] }"#,
+    ] {
+      let text = format!("name = \"foo\"\nargs = [{typ}]\n");
+      assert!(parse_toml(&text).is_err(), "{typ}");
+    }
+    let text = "name = \"foo\"\nargs = [{ name = \"X\", optinal = true }]\n";
+    let Err(DeserError::Toml { message, .. }) = parse_toml(text) else {
+      panic!("Should've failed");
+    };
+    assert!(message.contains("optinal"), "{message}");
+  }
+
+  #[test]
+  fn test_error_span() {
+    let text =
+      "name = \"foo\"\n[[flags]]\nforms = [\"-a\"]\ntype = \"paths\"\n";
+    let Err(DeserError::Toml {
+      span: Some(span), ..
+    }) = parse_toml(text)
+    else {
+      panic!("Should've failed");
+    };
+    assert_eq!(
+      r#""paths""#,
+      &text[span.offset()..span.offset() + span.len()]
+    );
+  }
+}
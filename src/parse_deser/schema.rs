@@ -0,0 +1,299 @@
+//! A JSON Schema for config files, which is used to validate JSON input and
+//! can be given to editors for autocompletion
+//!
+//! The schema is generated from [`CommandInfo`]. A copy is kept at
+//! `schemas/command.json` so that it can be linked to.
+
+use std::sync::OnceLock;
+
+use jsonschema::{paths::PathChunk, JSONSchema};
+use miette::{Diagnostic, SourceSpan};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::error::DeserError;
+use crate::CommandInfo;
+
+/// Where the schema for config files can be found
+pub const SCHEMA_URL: &str = "https://raw.githubusercontent.com/ysthakur/gen-completions/main/schemas/command.json";
+
+/// A place where some JSON didn't match the schema
+#[derive(Debug, Diagnostic, Error)]
+#[error("{message} (at {path})")]
+#[diagnostic(code(gen_completions::deser::schema_mismatch), url(docsrs))]
+pub struct SchemaError {
+  pub message: String,
+  /// The JSON pointer to the offending value, e.g. `/flags/0/forms`
+  pub path: String,
+  #[label("{message}")]
+  pub span: Option<SourceSpan>,
+}
+
+/// Generate the JSON Schema for config files
+#[must_use]
+pub fn json_schema() -> Value {
+  let mut schema = serde_json::to_value(schemars::schema_for!(CommandInfo))
+    .expect("Schema should've been serialized to JSON");
+  schema["$id"] = SCHEMA_URL.into();
+  // Args can also be just a type (see `Arg`'s `Deserialize` impl), which
+  // schemars can't tell from the derive
+  let arg = schema["definitions"]["Arg"].take();
+  schema["definitions"]["Arg"] = serde_json::json!({
+    "anyOf": [{ "$ref": "#/definitions/ArgType" }, arg]
+  });
+  schema
+}
+
+/// Parse JSON text as a [`CommandInfo`], checking it against the schema first
+/// so that errors can point to where the problem is
+pub(super) fn parse_json(text: &str) -> Result<CommandInfo, DeserError> {
+  let value = parse_value(text)?;
+  validate(text, &value, &[])?;
+  Ok(serde_json::from_value(value)?)
+}
+
+/// Parse JSON text as either a single [`CommandInfo`] or an array of them,
+/// checking each one against the schema
+pub(super) fn parse_json_all(
+  text: &str,
+) -> Result<Vec<CommandInfo>, DeserError> {
+  let value = parse_value(text)?;
+  if let Value::Array(values) = value {
+    values
+      .into_iter()
+      .enumerate()
+      .map(|(i, value)| {
+        validate(text, &value, &[PathChunk::Index(i)])?;
+        Ok(serde_json::from_value(value)?)
+      })
+      .collect()
+  } else {
+    validate(text, &value, &[])?;
+    Ok(vec![serde_json::from_value(value)?])
+  }
+}
+
+fn parse_value(text: &str) -> Result<Value, DeserError> {
+  serde_json::from_str(text).map_err(|error| DeserError::JsonSyntax {
+    span: line_col_offset(text, error.line(), error.column()).into(),
+    error,
+  })
+}
+
+/// Check a value against the schema. `prefix` is the path to the value inside
+/// `text`.
+fn validate(
+  text: &str,
+  value: &Value,
+  prefix: &[PathChunk],
+) -> Result<(), DeserError> {
+  static SCHEMA: OnceLock<JSONSchema> = OnceLock::new();
+  let schema = SCHEMA.get_or_init(|| {
+    JSONSchema::compile(&json_schema()).expect("Schema should be valid")
+  });
+  if let Err(errors) = schema.validate(value) {
+    let errors = errors
+      .map(|error| {
+        let path = prefix
+          .iter()
+          .cloned()
+          .chain(error.instance_path.iter().cloned())
+          .collect::<Vec<_>>();
+        SchemaError {
+          message: error.to_string(),
+          path: jsonschema::paths::JSONPointer::from(path.as_slice())
+            .to_string(),
+          span: find_span(text, &path),
+        }
+      })
+      .collect();
+    return Err(DeserError::Schema { errors });
+  }
+  Ok(())
+}
+
+/// Convert a 1-based line and column (as given by `serde_json`) to a byte
+/// offset
+fn line_col_offset(text: &str, line: usize, col: usize) -> usize {
+  let line_start = text
+    .split_inclusive('\n')
+    .take(line.saturating_sub(1))
+    .map(str::len)
+    .sum::<usize>();
+  (line_start + col.saturating_sub(1)).min(text.len())
+}
+
+/// Find the span of the value at the given path in some (valid) JSON text
+fn find_span(text: &str, path: &[PathChunk]) -> Option<SourceSpan> {
+  let bytes = text.as_bytes();
+  let mut pos = skip_ws(bytes, 0);
+  for chunk in path {
+    pos = match chunk {
+      PathChunk::Property(key) => find_key(text, pos, key)?,
+      PathChunk::Index(ind) => find_index(bytes, pos, *ind)?,
+      PathChunk::Keyword(_) => return None,
+    };
+  }
+  let end = skip_value(bytes, pos)?;
+  Some((pos, end - pos).into())
+}
+
+/// Find the value for a key in the object starting at `pos`
+fn find_key(text: &str, pos: usize, key: &str) -> Option<usize> {
+  let bytes = text.as_bytes();
+  if bytes.get(pos) != Some(&b'{') {
+    return None;
+  }
+  let mut pos = skip_ws(bytes, pos + 1);
+  while bytes.get(pos) == Some(&b'"') {
+    let key_end = skip_string(bytes, pos)?;
+    let found = serde_json::from_str::<String>(&text[pos..key_end]).ok()?;
+    pos = skip_ws(bytes, key_end);
+    if bytes.get(pos) != Some(&b':') {
+      return None;
+    }
+    pos = skip_ws(bytes, pos + 1);
+    if found == key {
+      return Some(pos);
+    }
+    pos = skip_ws(bytes, skip_value(bytes, pos)?);
+    if bytes.get(pos) == Some(&b',') {
+      pos = skip_ws(bytes, pos + 1);
+    }
+  }
+  None
+}
+
+/// Find the element at some index in the array starting at `pos`
+fn find_index(bytes: &[u8], pos: usize, ind: usize) -> Option<usize> {
+  if bytes.get(pos) != Some(&b'[') {
+    return None;
+  }
+  let mut pos = skip_ws(bytes, pos + 1);
+  for _ in 0..ind {
+    pos = skip_ws(bytes, skip_value(bytes, pos)?);
+    if bytes.get(pos) != Some(&b',') {
+      return None;
+    }
+    pos = skip_ws(bytes, pos + 1);
+  }
+  Some(pos)
+}
+
+/// Find the end of the value starting at `pos`
+fn skip_value(bytes: &[u8], pos: usize) -> Option<usize> {
+  match bytes.get(pos)? {
+    b'"' => skip_string(bytes, pos),
+    b'{' | b'[' => {
+      let mut depth = 0;
+      let mut pos = pos;
+      loop {
+        match bytes.get(pos)? {
+          b'"' => {
+            pos = skip_string(bytes, pos)?;
+            continue;
+          }
+          b'{' | b'[' => depth += 1,
+          b'}' | b']' => {
+            depth -= 1;
+            if depth == 0 {
+              return Some(pos + 1);
+            }
+          }
+          _ => {}
+        }
+        pos += 1;
+      }
+    }
+    // Numbers, booleans, and null
+    _ => Some(
+      pos
+        + bytes[pos..]
+          .iter()
+          .position(|b| {
+            matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace()
+          })
+          .unwrap_or(bytes.len() - pos),
+    ),
+  }
+}
+
+/// Find the end of the string starting at `pos`
+fn skip_string(bytes: &[u8], pos: usize) -> Option<usize> {
+  let mut pos = pos + 1;
+  loop {
+    match bytes.get(pos)? {
+      b'\\' => pos += 2,
+      b'"' => return Some(pos + 1),
+      _ => pos += 1,
+    }
+  }
+}
+
+fn skip_ws(bytes: &[u8], pos: usize) -> usize {
+  pos
+    + bytes[pos.min(bytes.len())..]
+      .iter()
+      .take_while(|b| b.is_ascii_whitespace())
+      .count()
+}
+
+#[cfg(test)]
+mod tests {
+  use miette::SourceSpan;
+
+  use super::{json_schema, parse_json, parse_json_all};
+  use crate::parse_deser::error::DeserError;
+
+  #[test]
+  fn schema_up_to_date() {
+    let saved: serde_json::Value =
+      serde_json::from_str(include_str!("../../schemas/command.json")).unwrap();
+    assert!(
+      saved == json_schema(),
+      "schemas/command.json is out of date, run `gen-completions schema > schemas/command.json`"
+    );
+  }
+
+  #[test]
+  fn error_spans() {
+    let text = r#"{
+      "name": "foo",
+      "flags": [
+        {"forms": ["-a"]},
+        {"forms": "-b"}
+      ]
+    }"#;
+    let Err(DeserError::Schema { errors }) = parse_json(text) else {
+      panic!("Should've failed to validate");
+    };
+    assert_eq!(1, errors.len());
+    assert_eq!("/flags/1/forms", errors[0].path);
+    let span = errors[0].span.unwrap();
+    assert_eq!(r#""-b""#, &text[span.offset()..span.offset() + span.len()]);
+  }
+
+  #[test]
+  fn array_error_spans() {
+    let text = r#"[
+      {"name": "foo"},
+      {"name": "bar", "flags": [{"forms": "-b"}]}
+    ]"#;
+    let Err(DeserError::Schema { errors }) = parse_json_all(text) else {
+      panic!("Should've failed to validate");
+    };
+    assert_eq!(1, errors.len());
+    assert_eq!("/1/flags/0/forms", errors[0].path);
+    let span = errors[0].span.unwrap();
+    assert_eq!(r#""-b""#, &text[span.offset()..span.offset() + span.len()]);
+  }
+
+  #[test]
+  fn syntax_error_span() {
+    let text = "{\n  \"name\": \"foo\",\n  oops\n}";
+    let Err(DeserError::JsonSyntax { span, .. }) = parse_json(text) else {
+      panic!("Should've been a syntax error");
+    };
+    assert_eq!(SourceSpan::from(text.find("oops").unwrap()), span);
+  }
+}
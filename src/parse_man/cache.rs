@@ -0,0 +1,299 @@
+//! An on-disk cache of parsed man pages, so that only commands whose man pages
+//! changed need to be parsed again
+//!
+//! Each command gets a JSON file in the cache directory holding the parsed
+//! [`CommandInfo`], along with the path, modification time, and content hash of
+//! every man page it was parsed from. The (sub)command each man page was
+//! detected as is stored too, so unchanged man pages don't need to be read
+//! again just to detect subcommands. Entries made with different explicitly
+//! given subcommands are ignored, since those change how man pages are
+//! grouped into commands.
+
+use std::{
+  collections::HashMap,
+  fs, io,
+  path::{Path, PathBuf},
+  time::UNIX_EPOCH,
+};
+
+use log::{debug, trace};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use super::CmdPreInfo;
+use crate::CommandInfo;
+
+/// A cache of parsed man pages
+pub struct Cache {
+  dir: PathBuf,
+  /// Hash of the explicitly given subcommands
+  inputs: u64,
+}
+
+/// What's stored in the cache for a single command
+#[derive(Deserialize, Serialize)]
+struct Entry<C> {
+  /// The version of gen-completions that parsed the man pages, since newer
+  /// versions may parse them differently
+  version: String,
+  /// Hash of the explicitly given subcommands the entry was made with
+  inputs: u64,
+  pages: Vec<PageStamp>,
+  cmd: C,
+}
+
+/// Information to tell if a man page changed
+#[derive(Deserialize, Serialize)]
+pub struct PageStamp {
+  path: PathBuf,
+  /// The command and subcommands the man page was detected as being for, e.g.
+  /// `["git", "log"]` for `git-log.1`
+  cmd: Vec<String>,
+  /// Modification time, in nanoseconds since the Unix epoch
+  mtime: u128,
+  /// Hash of the (possibly compressed) contents of the man page
+  hash: u64,
+}
+
+impl PageStamp {
+  fn new(path: &Path, cmd: Vec<String>) -> io::Result<Self> {
+    Ok(PageStamp {
+      path: path.to_path_buf(),
+      cmd,
+      mtime: mtime(path)?,
+      hash: hash(&fs::read(path)?),
+    })
+  }
+
+  /// Check if the man page is unchanged, first by checking its modification
+  /// time, then by checking its contents if the modification time changed
+  fn is_fresh(&self) -> bool {
+    match mtime(&self.path) {
+      Ok(mtime) if mtime == self.mtime => true,
+      Ok(_) => fs::read(&self.path).is_ok_and(|text| hash(&text) == self.hash),
+      Err(_) => false,
+    }
+  }
+}
+
+impl Cache {
+  /// Make a cache that stores its entries in the given directory.
+  /// `subcmds` are the explicitly given subcommands that will be passed to
+  /// [`detect_subcommands`](super::detect_subcommands).
+  pub fn new(
+    dir: impl Into<PathBuf>,
+    subcmds: &[(String, Vec<String>)],
+  ) -> Self {
+    let mut subcmds = subcmds.to_vec();
+    subcmds.sort();
+    let inputs = serde_json::to_vec(&subcmds)
+      .expect("Subcommands should've been serialized to JSON");
+    Cache {
+      dir: dir.into(),
+      inputs: hash(&inputs),
+    }
+  }
+
+  /// Whether an entry was made by this version with the same inputs
+  fn is_current<C>(&self, entry: &Entry<C>) -> bool {
+    entry.version == env!("CARGO_PKG_VERSION") && entry.inputs == self.inputs
+  }
+
+  /// The default cache directory, `$XDG_CACHE_HOME/gen-completions`
+  /// (`~/.cache/gen-completions` if `$XDG_CACHE_HOME` isn't set)
+  #[must_use]
+  pub fn default_dir() -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache"))
+      })?;
+    Some(cache_home.join("gen-completions"))
+  }
+
+  fn entry_path(&self, cmd_name: &str) -> PathBuf {
+    self.dir.join(format!("{cmd_name}.json"))
+  }
+
+  /// The subcommands detected from man pages that haven't changed since they
+  /// were cached, keyed by the man pages' paths, to be passed to
+  /// [`detect_subcommands`](super::detect_subcommands)
+  ///
+  /// This only checks modification times (and hashes, if those changed), so
+  /// it's much cheaper than reading and decompressing every man page.
+  #[must_use]
+  pub fn detected_subcmds(&self) -> HashMap<PathBuf, Vec<String>> {
+    let Ok(files) = fs::read_dir(&self.dir) else {
+      return HashMap::new();
+    };
+    let mut subcmds = HashMap::new();
+    for file in files.flatten() {
+      let Ok(text) = fs::read_to_string(file.path()) else {
+        continue;
+      };
+      let Ok(entry) = serde_json::from_str::<Entry<IgnoredAny>>(&text) else {
+        continue;
+      };
+      if !self.is_current(&entry) {
+        continue;
+      }
+      for stamp in entry.pages {
+        if stamp.is_fresh() {
+          subcmds.insert(stamp.path, stamp.cmd);
+        }
+      }
+    }
+    subcmds
+  }
+
+  /// Record the current state of a command's man pages. This should be done
+  /// before parsing them, so that a man page changed during parsing is seen
+  /// as stale next time.
+  ///
+  /// # Errors
+  ///
+  /// Fails if the man pages can't be read
+  pub fn stamps(
+    cmd_name: &str,
+    pre_info: &CmdPreInfo,
+  ) -> io::Result<Vec<PageStamp>> {
+    fn add(
+      cmd: Vec<String>,
+      pre_info: &CmdPreInfo,
+      stamps: &mut Vec<PageStamp>,
+    ) -> io::Result<()> {
+      if let Some(path) = &pre_info.path {
+        stamps.push(PageStamp::new(path, cmd.clone())?);
+      }
+      for (sub_name, sub_info) in &pre_info.subcmds {
+        let mut sub_cmd = cmd.clone();
+        sub_cmd.push(sub_name.clone());
+        add(sub_cmd, sub_info, stamps)?;
+      }
+      Ok(())
+    }
+
+    let mut stamps = Vec::new();
+    add(vec![cmd_name.to_owned()], pre_info, &mut stamps)?;
+    stamps.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(stamps)
+  }
+
+  /// Get the cached [`CommandInfo`] for a command, if none of its man pages
+  /// changed since it was cached
+  #[must_use]
+  pub fn get(
+    &self,
+    cmd_name: &str,
+    pre_info: &CmdPreInfo,
+  ) -> Option<CommandInfo> {
+    let text = fs::read_to_string(self.entry_path(cmd_name)).ok()?;
+    let entry: Entry<CommandInfo> = match serde_json::from_str(&text) {
+      Ok(entry) => entry,
+      Err(e) => {
+        debug!("Ignoring invalid cache entry for {cmd_name}: {e}");
+        return None;
+      }
+    };
+
+    let pages = pre_info.pages();
+    if !self.is_current(&entry)
+      || entry.pages.len() != pages.len()
+      || !entry
+        .pages
+        .iter()
+        .zip(pages)
+        .all(|(stamp, page)| stamp.path == page && stamp.is_fresh())
+    {
+      trace!("Cache entry for {cmd_name} is stale");
+      return None;
+    }
+
+    Some(entry.cmd)
+  }
+
+  /// Cache the [`CommandInfo`] parsed from man pages with the given stamps
+  /// (from [`Cache::stamps`])
+  ///
+  /// # Errors
+  ///
+  /// Fails if the cache directory can't be written
+  pub fn insert(
+    &self,
+    cmd_name: &str,
+    pages: Vec<PageStamp>,
+    cmd: &CommandInfo,
+  ) -> io::Result<()> {
+    let entry = Entry {
+      version: env!("CARGO_PKG_VERSION").to_owned(),
+      inputs: self.inputs,
+      pages,
+      cmd,
+    };
+    fs::create_dir_all(&self.dir)?;
+    let text = serde_json::to_string(&entry)
+      .expect("Cache entry should've been serialized to JSON");
+    fs::write(self.entry_path(cmd_name), text)
+  }
+}
+
+fn mtime(path: &Path) -> io::Result<u128> {
+  let modified = fs::metadata(path)?.modified()?;
+  Ok(
+    modified
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |time| time.as_nanos()),
+  )
+}
+
+/// FNV-1a, which is stable across Rust versions, unlike `DefaultHasher`
+fn hash(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, fs};
+
+  use super::Cache;
+  use crate::{parse_man::detect_subcommands, CommandInfo};
+
+  #[test]
+  fn detected_subcmds() {
+    let man_dir = tempfile::tempdir().unwrap();
+    let cached_page = man_dir.path().join("a/foo-bar.1");
+    let other_page = man_dir.path().join("b/foo-bar.1");
+    for page in [&cached_page, &other_page] {
+      fs::create_dir_all(page.parent().unwrap()).unwrap();
+    }
+    fs::write(&cached_page, ".TH FOO\\-BAR\nfoo bar does things").unwrap();
+    fs::write(&other_page, ".TH FOO\\-BAR\nnot a subcommand").unwrap();
+
+    let cache_dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(cache_dir.path(), &[]);
+    let cmds = detect_subcommands([&cached_page], Vec::new(), &HashMap::new());
+    let stamps = Cache::stamps("foo", &cmds["foo"]).unwrap();
+    let cmd = CommandInfo {
+      name: "foo".to_owned(),
+      ..Default::default()
+    };
+    cache.insert("foo", stamps, &cmd).unwrap();
+
+    // Only the page that was actually cached is reused
+    let detected = cache.detected_subcmds();
+    assert_eq!(
+      Some(&vec!["foo".to_owned(), "bar".to_owned()]),
+      detected.get(&cached_page)
+    );
+    let cmds = detect_subcommands([&other_page], Vec::new(), &detected);
+    assert!(cmds.contains_key("foo-bar"));
+
+    // Entries made with other explicit subcommands aren't used
+    let subcmds = [("foo-bar".to_owned(), vec!["foo-bar".to_owned()])];
+    assert!(Cache::new(cache_dir.path(), &subcmds)
+      .detected_subcmds()
+      .is_empty());
+  }
+}
@@ -0,0 +1,238 @@
+//! For finding subcommands listed in a COMMANDS or SUBCOMMANDS section, for
+//! tools like `ip`, `systemctl`, or `docker` that don't have a separate man
+//! page for each subcommand
+
+use log::debug;
+use regex::{Regex, RegexBuilder};
+
+use super::util;
+use crate::{CommandInfo, Flag};
+
+/// Parse the subcommands in the COMMANDS/SUBCOMMANDS section of a man page,
+/// along with any flags documented under each one
+///
+/// Subcommands may either have their own subsection (`.SS verb`) or be
+/// listed as paragraphs (`.TP`, `.PP`, `.IP`) with a description after them.
+/// Flags listed after a subcommand are assumed to belong to it.
+pub fn parse(cmd_name: &str, page_text: &str) -> Vec<CommandInfo> {
+  let section_re =
+    RegexBuilder::new(r#"^\.SH "?(SUB)?COMMANDS"?[ \t]*$(.*?)(^\.SH|\z)"#)
+      .multi_line(true)
+      .dot_matches_new_line(true)
+      .build()
+      .expect("Regex should be valid");
+  let Some(content) = section_re
+    .captures(page_text)
+    .map(|captures| captures.get(2).unwrap().as_str())
+  else {
+    return Vec::new();
+  };
+
+  let verb_re =
+    Regex::new(r"^[a-z][a-z0-9_-]*$").expect("Regex should be valid");
+  // The last word of the command name, e.g. `link` in `ip link`. This lets us
+  // skip the command name in headings like `.SS "ip link"`
+  let own_name = cmd_name.split(' ').next_back().unwrap_or(cmd_name);
+
+  let mut subcmds: Vec<CommandInfo> = Vec::new();
+
+  let mut subsections = content.split("\n.SS");
+  // Paragraphs before the first subsection
+  if let Some(intro) = subsections.next() {
+    parse_items(intro, own_name, &verb_re, &mut subcmds, None);
+  }
+  for subsection in subsections {
+    let (heading, body) =
+      subsection.split_once('\n').unwrap_or((subsection, ""));
+    let heading = util::remove_groff_formatting(heading);
+    let heading = heading.trim().trim_matches('"');
+    // Headings like "Unit Commands" just group subcommands together, but
+    // headings like "link" or "ip link" are for a single subcommand
+    let verb = verb_name(heading, own_name, &verb_re);
+    match verb {
+      Some(verb) => {
+        let ind = find_or_insert(&mut subcmds, &verb);
+        parse_items(body, own_name, &verb_re, &mut subcmds, Some(ind));
+        if subcmds[ind].desc.is_none() {
+          subcmds[ind].desc = intro_desc(body);
+        }
+      }
+      None => parse_items(body, own_name, &verb_re, &mut subcmds, None),
+    }
+  }
+
+  if !subcmds.is_empty() {
+    debug!(
+      "Found subcommands {} for {cmd_name} in COMMANDS section",
+      subcmds
+        .iter()
+        .map(|cmd| cmd.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+    );
+  }
+
+  subcmds
+}
+
+/// Parse the paragraphs inside a section or subsection. Paragraphs starting
+/// with a verb are treated as subcommands, while paragraphs starting with a
+/// dash are treated as flags for the last subcommand.
+///
+/// ## Arguments
+/// * `owner` - The subcommand that this whole subsection is for, if any
+fn parse_items(
+  text: &str,
+  own_name: &str,
+  verb_re: &Regex,
+  subcmds: &mut Vec<CommandInfo>,
+  owner: Option<usize>,
+) {
+  let mut last_verb = owner;
+  for (term, desc) in split_items(text) {
+    let term = util::remove_groff_formatting(&term);
+    let term = term.trim();
+    if term.starts_with('-') {
+      if let Some(ind) = last_verb {
+        let desc = util::remove_groff_formatting(&desc);
+        for flag in util::make_flag(term, Some(&desc)) {
+          push_flag(&mut subcmds[ind], flag);
+        }
+      }
+    } else if owner.is_none() {
+      if let Some(verb) = verb_name(term, own_name, verb_re) {
+        let ind = find_or_insert(subcmds, &verb);
+        if subcmds[ind].desc.is_none() {
+          subcmds[ind].desc =
+            util::clean_desc(&util::remove_groff_formatting(&desc));
+        }
+        last_verb = Some(ind);
+      }
+    }
+  }
+}
+
+/// Split text into paragraphs, returning the term (first line) and the rest
+/// of each paragraph
+fn split_items(text: &str) -> Vec<(String, String)> {
+  // Macros that don't start a new paragraph and can be dropped
+  let ignored_re = Regex::new(r"^\.(RS|RE|INDENT|UNINDENT|sp|br|in|PD|ne)\b")
+    .expect("Regex should be valid");
+
+  let mut items = Vec::new();
+  // The current term and the lines of its description
+  let mut curr: Option<(Option<String>, Vec<&str>)> = None;
+
+  for line in text.lines() {
+    let para = [".TP", ".PP", ".LP", ".HP", ".IP", ".P"]
+      .into_iter()
+      .find(|mac| line == *mac || line.starts_with(&format!("{mac} ")));
+    if let Some(mac) = para {
+      if let Some((Some(term), desc)) = curr.take() {
+        items.push((term, desc.join("\n")));
+      }
+      // `.IP "term" 4` has the term right there
+      let arg = line[mac.len()..].trim();
+      let term = if mac == ".IP" && !arg.is_empty() {
+        let arg = arg
+          .strip_prefix('"')
+          .map_or(arg, |arg| arg.split_once('"').map_or(arg, |(term, _)| term));
+        Some(arg.to_string())
+      } else {
+        None
+      };
+      curr = Some((term, Vec::new()));
+    } else if !ignored_re.is_match(line) {
+      if let Some((term, desc)) = curr.as_mut() {
+        if term.is_none() {
+          *term = Some(line.to_string());
+        } else {
+          desc.push(line);
+        }
+      }
+    }
+  }
+  if let Some((Some(term), desc)) = curr {
+    items.push((term, desc.join("\n")));
+  }
+
+  items
+}
+
+/// Get the name of the subcommand from a heading or term like `link`,
+/// `ip link`, `start UNIT...` or `list, ls FILE`
+///
+/// Returns `None` if the term looks like prose rather than a subcommand
+/// followed by its arguments.
+fn verb_name(term: &str, own_name: &str, verb_re: &Regex) -> Option<String> {
+  let mut words = term.split_whitespace().peekable();
+  if words.peek() == Some(&own_name) {
+    words.next();
+  }
+
+  let first = words.next()?;
+  let verb = first.trim_end_matches(',');
+  if !verb_re.is_match(verb) {
+    return None;
+  }
+
+  // Skip aliases like `ls` in `list, ls`
+  let mut prev = first;
+  while prev.ends_with(',') {
+    prev = words.next()?;
+  }
+
+  // Whatever's after the verb should be arguments, not lowercase words,
+  // unless they're inside brackets
+  let mut depth = 0usize;
+  for word in words {
+    let is_word = depth == 0 && word.starts_with(|c: char| c.is_lowercase());
+    for c in word.chars() {
+      match c {
+        '[' | '<' | '{' | '(' => depth += 1,
+        ']' | '>' | '}' | ')' => depth = depth.saturating_sub(1),
+        _ => {}
+      }
+    }
+    if is_word {
+      return None;
+    }
+  }
+
+  Some(verb.to_string())
+}
+
+/// The description for a subsection, taken from the text before its first
+/// paragraph
+fn intro_desc(body: &str) -> Option<String> {
+  let intro = body
+    .lines()
+    .take_while(|line| !line.starts_with('.'))
+    .collect::<Vec<_>>()
+    .join("\n");
+  util::clean_desc(&util::remove_groff_formatting(&intro))
+}
+
+/// Find the subcommand with the given name, adding it if it's not there
+fn find_or_insert(subcmds: &mut Vec<CommandInfo>, name: &str) -> usize {
+  if let Some(ind) = subcmds.iter().position(|cmd| cmd.name == name) {
+    ind
+  } else {
+    subcmds.push(CommandInfo {
+      name: name.to_string(),
+      ..Default::default()
+    });
+    subcmds.len() - 1
+  }
+}
+
+/// Add a flag to a subcommand, unless it's already there
+fn push_flag(cmd: &mut CommandInfo, flag: Flag) {
+  if !cmd
+    .flags
+    .iter()
+    .any(|other| other.forms.iter().any(|form| flag.forms.contains(form)))
+  {
+    cmd.flags.push(flag);
+  }
+}
@@ -36,9 +36,7 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
         let desc = desc.replace(".Nm", cmd_name);
         desc_end.replace(&desc, "").to_string()
       });
-      if let Some(flag) = util::make_flag(options, desc.as_deref()) {
-        flags.push(flag);
-      }
+      flags.extend(util::make_flag(options, desc.as_deref()));
     }
   }
 
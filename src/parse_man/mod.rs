@@ -1,6 +1,9 @@
 //! For parsing command information from man pages
+pub mod cache;
+mod commands;
 mod darwin;
 pub mod error;
+mod option_pattern;
 mod podman;
 mod scdoc;
 mod type1;
@@ -19,6 +22,7 @@ use std::{
 use bzip2::bufread::BzDecoder;
 use flate2::bufread::GzDecoder;
 use log::{debug, trace};
+use rayon::prelude::*;
 
 use crate::{parse_man::error::Error, CommandInfo, Flag};
 
@@ -27,9 +31,26 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Information about a command and its detected subcommands before being parsed
 pub struct CmdPreInfo {
   path: Option<PathBuf>,
+  /// The decompressed text of the man page, if it was already read while
+  /// detecting subcommands
+  text: Option<String>,
   subcmds: HashMap<String, CmdPreInfo>,
 }
 
+impl CmdPreInfo {
+  /// The paths of the man pages for this command and all its subcommands,
+  /// sorted
+  #[must_use]
+  pub fn pages(&self) -> Vec<PathBuf> {
+    let mut pages = self.path.iter().cloned().collect::<Vec<_>>();
+    for subcmd in self.subcmds.values() {
+      pages.extend(subcmd.pages());
+    }
+    pages.sort();
+    pages
+  }
+}
+
 /// Get the command that a manpage is for, given its path
 ///
 /// e.g. `/foo/cowsay.1.txt -> "cowsay"`
@@ -110,16 +131,22 @@ pub fn parse_from(
   let mut subcommands = Vec::new();
   let mut errors = Vec::new();
 
+  // Subcommands listed in a COMMANDS section rather than in their own man page
+  let mut listed_subcmds = Vec::new();
+
   let flags = if let Some(path) = pre_info.path {
-    match read_manpage(path.clone()) {
+    let text = match pre_info.text {
+      Some(text) => Ok(text),
+      None => read_manpage(&path),
+    };
+    match text {
       Ok(text) => {
+        listed_subcmds = commands::parse(cmd_name, &text);
         let all_flags = parse_manpage_text(cmd_name, text);
-        if all_flags.is_empty() {
+        if all_flags.is_empty() && listed_subcmds.is_empty() {
           errors.push(Error::UnsupportedFormat { path });
-          Vec::new()
-        } else {
-          all_flags
         }
+        all_flags
       }
       Err(e) => {
         errors.push(e.into());
@@ -142,43 +169,75 @@ pub fn parse_from(
     errors.append(&mut sub_errors);
   }
 
+  // Subcommands with their own man pages take precedence
+  for subcmd in listed_subcmds {
+    if !subcommands.iter().any(|other| other.name == subcmd.name) {
+      subcommands.push(subcmd);
+    }
+  }
+
   let cmd_info = if flags.is_empty() && subcommands.is_empty() {
     None
   } else {
     subcommands.sort_by(|a, b| a.name.cmp(&b.name));
     Some(CommandInfo {
-      name: cmd_name.split(' ').last().unwrap().to_string(),
+      name: cmd_name.split(' ').next_back().unwrap().to_string(),
       desc: None,
       flags,
       args,
       subcommands,
+      ..Default::default()
     })
   };
   (cmd_info, errors)
 }
 
 /// Make a tree relating commands to their subcommands
+///
+/// Man pages are read in parallel. The text of any man page that had to be
+/// read is kept so that [`parse_from`] doesn't need to read it again.
+/// `detected` holds the subcommands already detected from the man pages at the
+/// given paths (see [`cache::Cache::detected_subcmds`]), so that those pages
+/// don't need to be read at all.
 #[must_use]
 pub fn detect_subcommands(
   manpages: impl IntoIterator<Item = impl AsRef<Path>>,
   explicit_subcmds: impl IntoIterator<Item = (String, Vec<String>)>,
+  detected: &HashMap<PathBuf, Vec<String>>,
 ) -> HashMap<String, CmdPreInfo> {
-  let mut explicit_subcmds: HashMap<_, _> =
-    explicit_subcmds.into_iter().collect();
+  let explicit_subcmds: HashMap<_, _> = explicit_subcmds.into_iter().collect();
 
-  let mut res = HashMap::new();
+  let mut manpages = manpages
+    .into_iter()
+    .map(|page| PathBuf::from(page.as_ref()))
+    .collect::<Vec<_>>();
+  // Sort so that the tree is the same no matter what order the man pages were
+  // found in
+  manpages.sort();
 
-  for page in manpages {
-    let page = PathBuf::from(page.as_ref());
-    let cmd_name = get_cmd_name(&page);
-    match explicit_subcmds.remove(&cmd_name) {
-      Some(as_subcmd) => insert_subcmd(&mut res, as_subcmd, page),
-      None => {
-        if let Ok(text) = read_manpage(&page) {
-          insert_subcmd(&mut res, detect_subcommand(&cmd_name, &text), page);
-        }
+  let detected = manpages
+    .into_par_iter()
+    .filter_map(|page| {
+      let cmd_name = get_cmd_name(&page);
+      if let Some(as_subcmd) = explicit_subcmds.get(&cmd_name) {
+        Some((as_subcmd.clone(), page, None))
+      } else if let Some(as_subcmd) = detected.get(&page) {
+        Some((as_subcmd.clone(), page, None))
+      } else if cmd_name.contains('-') {
+        // Only man pages for commands with hyphens need to be read to find out
+        // if they're subcommands
+        let text = read_manpage(&page).ok()?;
+        let as_subcmd = detect_subcommand(&cmd_name, &text);
+        Some((as_subcmd, page, Some(text)))
+      } else {
+        Some((vec![cmd_name], page, None))
       }
-    }
+    })
+    .collect::<Vec<_>>();
+
+  let mut res = HashMap::new();
+  for (as_subcmd, page, text) in detected {
+    insert_subcmd(&mut res, as_subcmd, page, text);
   }
 
   res
@@ -189,19 +248,22 @@ fn insert_subcmd(
   subcommands: &mut HashMap<String, CmdPreInfo>,
   mut cmd_parts: Vec<String>,
   path: PathBuf,
+  text: Option<String>,
 ) {
   let head = cmd_parts.remove(0);
   let cmd = match subcommands.entry(head) {
     Entry::Occupied(o) => o.into_mut(),
     Entry::Vacant(v) => v.insert(CmdPreInfo {
       path: None,
+      text: None,
       subcmds: HashMap::new(),
     }),
   };
   if cmd_parts.is_empty() {
     cmd.path = Some(path);
+    cmd.text = text;
   } else {
-    insert_subcmd(&mut cmd.subcmds, cmd_parts, path);
+    insert_subcmd(&mut cmd.subcmds, cmd_parts, path, text);
   }
 }
 
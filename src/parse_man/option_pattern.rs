@@ -0,0 +1,391 @@
+//! A small grammar for the option patterns found in man pages
+//!
+//! Man pages often squash several flags into one pattern, e.g.
+//! `--no-[to|cc|bc]` (from `git send-email`), `--[no-]verify`,
+//! `--color[=WHEN]`, or `-[0-9]`. This module expands such patterns into the
+//! individual forms they stand for.
+//!
+//! Roughly, a single option is parsed as
+//!
+//! ```text
+//! option := dashes (literal | group)* arg?
+//! group  := '[' alt ('|' alt)* ']' | '[' char '-' char ']'
+//! arg    := '=' NAME | '[=' NAME ']' | '[' NAME ']' | '<' NAME '>'
+//! ```
+//!
+//! A group with a single alternative is optional (`--foo[bar]` is `--foo` or
+//! `--foobar`), except for `[no-]`, which makes the option negatable.
+
+/// An argument that an option takes
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OptionArg {
+  /// The placeholder name for the value, e.g. `WHEN` in `--color[=WHEN]`
+  pub name: String,
+  /// Whether the value can be left out, e.g. `--color[=WHEN]`
+  pub optional: bool,
+}
+
+/// A single option pattern, after expansion
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OptionPattern {
+  /// All the forms the pattern expands to, e.g. `--no-to`, `--no-cc`, and
+  /// `--no-bc` for `--no-[to|cc|bc]`
+  pub forms: Vec<String>,
+  /// The negated versions of [`OptionPattern::forms`] for patterns like
+  /// `--[no-]verify`. Empty if the option isn't negatable.
+  pub negated: Vec<String>,
+  /// The argument to the option, if any
+  pub arg: Option<OptionArg>,
+}
+
+/// Characters to trim off the ends of an option or argument
+const TRIM_CHARS: &[char] =
+  &[' ', '\t', '\r', '\n', '(', ')', '{', '}', '.', ':', '!'];
+
+/// Common words that show up right after an option in prose (e.g.
+/// "--full-history with parent rewriting") and so can't be placeholders
+const PROSE_WORDS: &[&str] = &[
+  "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "if", "in",
+  "is", "it", "of", "on", "or", "the", "then", "to", "with", "without",
+];
+
+/// Parse a line of options, e.g. `-c, --color[=WHEN]` or `-o FILE`
+///
+/// The first word after an option that isn't an option itself (e.g. `FILE` in
+/// `-o FILE`) is taken to be the argument of that option. Leftover mdoc
+/// macros like `Ar` are skipped.
+pub fn parse_options(line: &str) -> Vec<OptionPattern> {
+  let mut patterns: Vec<OptionPattern> = Vec::new();
+  let mut after_option = false;
+
+  for word in split_words(line) {
+    let word = word.trim_matches(TRIM_CHARS);
+    if word.starts_with('-') {
+      if let Some(pattern) = parse_option(word) {
+        patterns.push(pattern);
+        after_option = true;
+      }
+    } else if is_mdoc_macro(word) {
+      continue;
+    } else if let Some(prev) = patterns.last_mut() {
+      // Optional options like `[-v]` are most likely from a synopsis, so they
+      // aren't arguments
+      if after_option && prev.arg.is_none() && !word.starts_with("[-") {
+        prev.arg = parse_arg(word);
+      }
+      after_option = false;
+    }
+  }
+
+  patterns
+}
+
+/// Parse a single option like `--[no-]verify` or `-[0-9]`
+///
+/// Returns `None` if it doesn't look like an option.
+pub fn parse_option(word: &str) -> Option<OptionPattern> {
+  let name_start = word.find(|c| c != '-')?;
+  let (dashes, rest) = word.split_at(name_start);
+  if dashes.len() > 2 {
+    return None;
+  }
+
+  // Each part is a set of alternatives, so the forms are the cartesian
+  // product of all the parts
+  let mut parts: Vec<Vec<String>> = Vec::new();
+  let mut literal = String::new();
+  let mut negatable = false;
+  let mut arg = None;
+
+  let mut chars = rest.char_indices().peekable();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '[' => {
+        let Some(len) = rest[i..].find(']') else {
+          // Unclosed bracket, so ignore everything after it
+          break;
+        };
+        let inner = &rest[i + 1..i + len];
+        let at_end = i + len + 1 == rest.len();
+        // Skip past the closing bracket
+        while chars.next_if(|&(j, _)| j <= i + len).is_some() {}
+
+        if let Some(name) = inner.strip_prefix('=') {
+          let name = name.trim_matches(&['<', '>'][..]);
+          arg = is_valid_arg_name(name).then(|| OptionArg {
+            name: name.to_string(),
+            optional: true,
+          });
+          break;
+        } else if at_end
+          && (!literal.is_empty() || !parts.is_empty())
+          && is_placeholder(inner)
+        {
+          // Something like `-n[NUM]`
+          arg = Some(OptionArg {
+            name: inner.to_string(),
+            optional: true,
+          });
+          break;
+        }
+
+        if inner == "no-" && literal.is_empty() && parts.is_empty() {
+          negatable = true;
+        } else if let Some(range) = expand_range(inner) {
+          parts.push(vec![std::mem::take(&mut literal)]);
+          parts.push(range);
+        } else if inner.contains('|') {
+          parts.push(vec![std::mem::take(&mut literal)]);
+          parts.push(inner.split('|').map(String::from).collect());
+        } else {
+          parts.push(vec![std::mem::take(&mut literal)]);
+          parts.push(vec![String::new(), inner.to_string()]);
+        }
+      }
+      '=' => {
+        arg = parse_arg(&rest[i + 1..]);
+        break;
+      }
+      '<' => {
+        arg = parse_arg(&rest[i..]);
+        break;
+      }
+      // Something like `--foo=(+|\-)x`, or garbage after the option
+      '(' | ')' | '{' | '}' | ']' => break,
+      c => literal.push(c),
+    }
+  }
+  parts.push(vec![literal]);
+
+  let forms = parts
+    .into_iter()
+    .fold(vec![String::new()], |prefixes, alts| {
+      prefixes
+        .iter()
+        .flat_map(|prefix| alts.iter().map(move |alt| format!("{prefix}{alt}")))
+        .collect()
+    })
+    .into_iter()
+    .map(|name| name.trim_end_matches(TRIM_CHARS).to_string())
+    .filter(|name| !name.is_empty())
+    .map(|name| format!("{dashes}{name}"))
+    .collect::<Vec<_>>();
+
+  if forms.is_empty() {
+    return None;
+  }
+
+  let negated = if negatable {
+    forms
+      .iter()
+      .map(|form| format!("{dashes}no-{}", &form[dashes.len()..]))
+      .collect()
+  } else {
+    Vec::new()
+  };
+
+  Some(OptionPattern {
+    forms,
+    negated,
+    arg,
+  })
+}
+
+/// Parse an argument like `FILE`, `<file>`, or `[FILE]`
+fn parse_arg(word: &str) -> Option<OptionArg> {
+  let word = word.trim_matches(TRIM_CHARS);
+  let (word, optional) = match word.strip_prefix('[') {
+    Some(inner) => (inner.trim_end_matches(']'), true),
+    None => (word, false),
+  };
+  let name = word.trim_start_matches('=').trim_matches(&['<', '>'][..]);
+  if is_valid_arg_name(name) {
+    Some(OptionArg {
+      name: name.to_string(),
+      optional,
+    })
+  } else {
+    None
+  }
+}
+
+/// Whether something can be the name of an argument. Rejects mangled
+/// placeholders like `name>=<value` and `(sorted|unsorted)`, prose like
+/// `with`, and mdoc macros like `Ar` that were left behind.
+fn is_valid_arg_name(name: &str) -> bool {
+  !name.is_empty()
+    && !name.contains(['=', '(', ')', '|'])
+    && !PROSE_WORDS.contains(&name)
+    && !is_mdoc_macro(name)
+}
+
+/// Whether something looks like an mdoc macro, e.g. `Ar` or `Op`
+fn is_mdoc_macro(word: &str) -> bool {
+  let mut chars = word.chars();
+  matches!(
+    (chars.next(), chars.next(), chars.next()),
+    (Some(first), Some(second), None)
+      if first.is_ascii_uppercase() && second.is_ascii_lowercase()
+  )
+}
+
+/// Expand a character range like `0-9` or `a-z`
+fn expand_range(inner: &str) -> Option<Vec<String>> {
+  let mut chars = inner.chars();
+  match (chars.next(), chars.next(), chars.next(), chars.next()) {
+    (Some(start), Some('-'), Some(end), None)
+      if start.is_ascii_alphanumeric()
+        && end.is_ascii_alphanumeric()
+        && start < end =>
+    {
+      Some((start..=end).map(String::from).collect())
+    }
+    _ => None,
+  }
+}
+
+/// Whether something looks like a placeholder for a value, e.g. `NUM` or
+/// `<file>`
+fn is_placeholder(s: &str) -> bool {
+  (s.starts_with('<') && s.ends_with('>'))
+    || (s.chars().any(|c| c.is_ascii_uppercase())
+      && s
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'))
+}
+
+/// Split a line of options into words on spaces, commas, quotes, and pipes,
+/// without splitting inside brackets
+fn split_words(line: &str) -> Vec<&str> {
+  let mut words = Vec::new();
+  let mut depth = 0usize;
+  let mut start = 0;
+
+  for (i, c) in line.char_indices() {
+    match c {
+      '[' | '<' => depth += 1,
+      ']' | '>' => depth = depth.saturating_sub(1),
+      ' ' | '\t' | '\n' | ',' | '"' | '|' if depth == 0 => {
+        if start < i {
+          words.push(&line[start..i]);
+        }
+        start = i + c.len_utf8();
+      }
+      _ => {}
+    }
+  }
+  if start < line.len() {
+    words.push(&line[start..]);
+  }
+
+  words
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::{parse_option, parse_options, OptionArg, OptionPattern};
+
+  fn strs(strs: &[&str]) -> Vec<String> {
+    strs.iter().map(|s| s.to_string()).collect()
+  }
+
+  #[test]
+  fn test_alternation() {
+    assert_eq!(
+      Some(OptionPattern {
+        forms: strs(&["--no-to", "--no-cc", "--no-bc"]),
+        negated: vec![],
+        arg: None,
+      }),
+      parse_option("--no-[to|cc|bc]")
+    );
+  }
+
+  #[test]
+  fn test_negatable() {
+    assert_eq!(
+      Some(OptionPattern {
+        forms: strs(&["--verify"]),
+        negated: strs(&["--no-verify"]),
+        arg: None,
+      }),
+      parse_option("--[no-]verify")
+    );
+  }
+
+  #[test]
+  fn test_range() {
+    assert_eq!(
+      strs(&["-0", "-1", "-2", "-3", "-4", "-5", "-6", "-7", "-8", "-9"]),
+      parse_option("-[0-9]").unwrap().forms
+    );
+  }
+
+  #[test]
+  fn test_optional_arg() {
+    assert_eq!(
+      Some(OptionPattern {
+        forms: strs(&["--color"]),
+        negated: vec![],
+        arg: Some(OptionArg {
+          name: "WHEN".to_owned(),
+          optional: true
+        }),
+      }),
+      parse_option("--color[=WHEN]")
+    );
+  }
+
+  #[test]
+  fn test_line() {
+    assert_eq!(
+      vec![
+        OptionPattern {
+          forms: strs(&["-o"]),
+          negated: vec![],
+          arg: Some(OptionArg {
+            name: "FILE".to_owned(),
+            optional: false
+          }),
+        },
+        OptionPattern {
+          forms: strs(&["--output"]),
+          negated: vec![],
+          arg: Some(OptionArg {
+            name: "file".to_owned(),
+            optional: false
+          }),
+        },
+      ],
+      parse_options("-o FILE, --output=<file>")
+    );
+  }
+
+  #[test]
+  fn test_bad_args() {
+    for line in [
+      "-c <name>=<value>",
+      "--no-walk[=(sorted|unsorted)]",
+      "--decorate[=short|full|auto|no]",
+      "--full-history with parent rewriting",
+      "--folder Ar",
+    ] {
+      let patterns = parse_options(line);
+      assert_eq!(1, patterns.len(), "{line}");
+      assert_eq!(None, patterns[0].arg, "{line}");
+    }
+  }
+
+  #[test]
+  fn test_skip_mdoc_macro() {
+    assert_eq!(
+      Some(OptionArg {
+        name: "directory".to_owned(),
+        optional: false
+      }),
+      parse_options("--folder Ar directory")[0].arg
+    );
+  }
+}
@@ -23,9 +23,7 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
         let data = data.trim();
         if let Some(start) = data.find(".IX Item") {
           if let Some((options, desc)) = data[start..].split_once('\n') {
-            if let Some(flag) = util::make_flag(options, Some(desc)) {
-              flags.push(flag);
-            }
+            flags.extend(util::make_flag(options, Some(desc)));
           } else if !data.is_empty() {
             warn!(
               "In command {cmd_name}, no description, data: {}",
@@ -20,9 +20,7 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
           let mut data = data.split(".RS 4");
           let options = data.next().unwrap();
           let desc = data.next();
-          if let Some(flag) = util::make_flag(options, desc) {
-            flags.push(flag);
-          }
+          flags.extend(util::make_flag(options, desc));
         } else {
           debug!(
             "In command {cmd_name}, no .RE found to end description, para: {}",
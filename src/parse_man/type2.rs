@@ -27,7 +27,7 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
         };
         let data = util::remove_groff_formatting(data);
         let data = data.trim();
-        let flag = if let Some((options, desc)) = data.split_once('\n') {
+        let new_flags = if let Some((options, desc)) = data.split_once('\n') {
           util::make_flag(options, Some(desc))
         } else {
           // todo should this be an error instead?
@@ -37,9 +37,7 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
           );
           util::make_flag(data, None)
         };
-        if let Some(flag) = flag {
-          flags.push(flag);
-        }
+        flags.extend(new_flags);
       }
 
       flags
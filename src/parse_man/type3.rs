@@ -39,17 +39,13 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
           // This means there is a .HP before the options
           let options = util::remove_groff_formatting(options);
           let desc = util::remove_groff_formatting(desc);
-          if let Some(flag) = util::make_flag(&options, Some(&desc)) {
-            flags.push(flag);
-          }
+          flags.extend(util::make_flag(&options, Some(&desc)));
         } else {
           // This means there is a .TP before the options
           let data = util::remove_groff_formatting(data);
           let data = data.trim();
           if let Some((options, desc)) = data.split_once('\n') {
-            if let Some(flag) = util::make_flag(options, Some(desc)) {
-              flags.push(flag);
-            }
+            flags.extend(util::make_flag(options, Some(desc)));
           } else {
             // todo should this be an error instead?
             debug!(
@@ -18,9 +18,7 @@ pub fn parse(cmd_name: &str, page_text: &str) -> Vec<Flag> {
         let data = util::remove_groff_formatting(para);
         let data = data.trim();
         if let Some((options, desc)) = data.split_once('\n') {
-          if let Some(flag) = util::make_flag(options, Some(desc)) {
-            flags.push(flag);
-          }
+          flags.extend(util::make_flag(options, Some(desc)));
         } else {
           warn!(
             "In command {cmd_name}, no description, data: {}",
@@ -3,7 +3,11 @@
 use log::debug;
 use regex::{Regex, RegexBuilder};
 
-use super::Flag;
+use super::{
+  option_pattern::{self, OptionArg},
+  Flag,
+};
+use crate::{ArgStyle, ArgType, FlagArg, ValueCount};
 
 /// Match roff numeric expressions
 pub static NUM_RE: &str = r"(\d+(\.\d)?)";
@@ -66,18 +70,13 @@ pub fn remove_groff_formatting(data: &str) -> String {
 
 /// Truncates to at most `len` characters, as well as trims and removes newlines
 pub fn truncate(s: &str, len: usize) -> String {
-  let s = s.trim().replace('\n', " ");
-  if s.len() > len {
-    s[0..len].to_string()
-  } else {
-    s
-  }
+  s.trim().replace('\n', " ").chars().take(len).collect()
 }
 
 /// Parse the line of options after .PP and the description after it
 ///
 /// Ported from Fish's `built_command`
-pub fn make_flag(options: &str, desc: Option<&str>) -> Option<Flag> {
+pub fn make_flag(options: &str, desc: Option<&str>) -> Vec<Flag> {
   // Unquote the options string
   let options = options.trim();
   let options = if options.len() < 2 {
@@ -90,28 +89,11 @@ pub fn make_flag(options: &str, desc: Option<&str>) -> Option<Flag> {
     options
   };
 
-  let mut forms = Vec::new();
-  let delim = Regex::new(r#"[ ,="|]"#).unwrap();
-  for option in delim.split(options) {
-    let option = Regex::new(r"\[.*\]").unwrap().replace(option, "");
-    // todo Fish doesn't replace <.*> or (.*) so maybe this is wrong
-    let option = Regex::new(r"<.*").unwrap().replace(&option, "");
-    // todo this is ridiculously verbose
-    let option = option
-      .trim_matches(" \t\r\n[](){}.:!".chars().collect::<Vec<_>>().as_slice());
-    // TODO in future, handle stuff like `--no-[to|cc|bc]` (example from `git
-    // send-email`)
-    // Trim stuff like `-[foo` and `-)foo` from the end
-    // Something like `--foo=(+|\-)x` would otherwise be read as --foo and -x
-    let option = Regex::new(r"-[()\[\]].*$").unwrap().replace(option, "");
-    if !option.starts_with('-') || option == "-" || option == "--" {
-      continue;
-    }
-    if Regex::new(r"\{\}\(\)").unwrap().is_match(&option) {
-      continue;
-    }
-    forms.push(option.to_string());
-  }
+  let patterns = option_pattern::parse_options(options);
+  let forms = patterns
+    .iter()
+    .flat_map(|pattern| pattern.forms.iter().cloned())
+    .collect::<Vec<_>>();
 
   if forms.is_empty() {
     let desc = if let Some(desc) = desc {
@@ -120,37 +102,175 @@ pub fn make_flag(options: &str, desc: Option<&str>) -> Option<Flag> {
       String::new()
     };
     debug!("No options found in '{}', desc: '{}'", options.trim(), desc);
-    return None;
+    return Vec::new();
+  }
+
+  let arg = patterns
+    .iter()
+    .find_map(|pattern| pattern.arg.as_ref())
+    .map(to_flag_arg);
+  // todo parse more flag types
+  let mut flags = vec![Flag {
+    forms,
+    desc: desc.and_then(clean_desc),
+    typ: arg
+      .as_ref()
+      .and_then(|arg| arg.name.as_deref())
+      .and_then(placeholder_type),
+    arg,
+    repeatable: desc.is_some_and(is_repeatable),
+    ..Default::default()
+  }];
+
+  // The negated forms of something like `--[no-]verify` get their own flag,
+  // since they do the opposite of the positive forms and don't take a value
+  let (positive, negated): (Vec<_>, Vec<_>) = patterns
+    .iter()
+    .filter(|pattern| !pattern.negated.is_empty())
+    .map(|pattern| (pattern.forms.clone(), pattern.negated.clone()))
+    .unzip();
+  if !negated.is_empty() {
+    flags.push(Flag {
+      forms: negated.concat(),
+      desc: Some(format!("Negate {}", positive.concat().join(", "))),
+      ..Default::default()
+    });
+  }
+
+  flags
+}
+
+/// Check if a flag's description says it can be given more than once, e.g.
+/// "This option may be repeated" or "can be specified multiple times"
+fn is_repeatable(desc: &str) -> bool {
+  let re = RegexBuilder::new(
+    r"\b(can|may)\s+be\s+(repeated|(given|specified|used|passed|supplied)\s+(multiple|several|many)\s+times|(given|specified|used|passed|supplied)\s+more\s+than\s+once)",
+  )
+  .case_insensitive(true)
+  .build()
+  .expect("Regex should be valid");
+  re.is_match(desc)
+}
+
+/// Turn the argument from an option pattern into a [`FlagArg`]. Optional
+/// values have to be in the same word as the flag (e.g. `--color=auto`),
+/// since otherwise they'd be taken as positional arguments.
+fn to_flag_arg(arg: &OptionArg) -> FlagArg {
+  let (name, variadic) = match arg.name.strip_suffix("...") {
+    Some(name) => (name, true),
+    None => (arg.name.as_str(), false),
+  };
+  let (count, style) = if arg.optional {
+    (ValueCount::Optional, ArgStyle::Attached)
+  } else if variadic {
+    (ValueCount::Variadic, ArgStyle::Any)
+  } else {
+    (ValueCount::One, ArgStyle::Any)
+  };
+  FlagArg {
+    count,
+    name: Some(name.to_string()),
+    style,
+  }
+}
+
+/// Guess a flag's type from the placeholder for its value, e.g. `FILE.tar`
+/// means a path ending in `.tar` and `N` means an integer. Extensions have to
+/// be lowercase so that placeholders like `MAJOR.MINOR` aren't mistaken for
+/// paths.
+fn placeholder_type(name: &str) -> Option<ArgType> {
+  let re = Regex::new(r"^[A-Za-z_-]+((\.[a-z][a-z0-9]*)+)$")
+    .expect("Regex should be valid");
+  if let Some(caps) = re.captures(name) {
+    return Some(ArgType::Glob {
+      globs: vec![format!("*{}", &caps[1])],
+      dirs: true,
+    });
+  }
+
+  let typ = match name.to_lowercase().replace('_', "-").as_str() {
+    "n" | "num" | "number" | "count" | "jobs" | "depth" | "width" | "lines"
+    | "seconds" | "secs" | "milliseconds" | "ms" => ArgType::Int {
+      min: None,
+      max: None,
+    },
+    "float" | "ratio" | "factor" | "fraction" => ArgType::Float,
+    "duration" | "timeout" | "interval" | "delay" => ArgType::Duration,
+    "url" | "uri" => ArgType::Url,
+    "pattern" | "regex" | "regexp" | "string" | "str" | "text" | "message"
+    | "msg" => ArgType::Text,
+    _ => return None,
+  };
+  Some(typ)
+}
+
+/// Clean up a description, removing subsection headings at the end, newlines,
+/// and bogus escapes. Returns `None` if nothing is left.
+pub fn clean_desc(desc: &str) -> Option<String> {
+  // Get rid of subsection headings at the end
+  let desc_end = RegexBuilder::new("\\.SS.*")
+    .dot_matches_new_line(true)
+    .build()
+    .expect("Regex should be valid");
+  let desc = desc_end.replace(desc, "");
+
+  let desc = desc.trim().replace('\n', " ");
+  let desc = desc.trim_end_matches('.');
+  // Remove bogus escapes
+  let desc = desc.replace(r"\'", "").replace(r"\.", "");
+
+  let desc = trim_desc(&desc);
+
+  if desc.is_empty() {
+    None
+  } else {
+    Some(desc)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{is_repeatable, placeholder_type, truncate};
+  use crate::ArgType;
+
+  #[test]
+  fn test_truncate() {
+    assert_eq!("foo bar", truncate(" foo\nbar ", 40));
+    // Multi-byte characters aren't split
+    assert_eq!("Café", truncate("Café au lait", 4));
+  }
+
+  #[test]
+  fn test_repeatable() {
+    assert!(is_repeatable(
+      "Increase verbosity. This option may be repeated."
+    ));
+    assert!(is_repeatable("Can be given\nmultiple times"));
+    assert!(is_repeatable("can be specified more than once"));
+    assert!(!is_repeatable("Only the last value given is used"));
+    assert!(!is_repeatable("This may be given as a number"));
   }
 
-  // todo parse flag types
-  match desc {
-    Some(desc) => {
-      // Get rid of subsection headings at the end
-      let desc_end = RegexBuilder::new("\\.SS.*")
-        .dot_matches_new_line(true)
-        .build()
-        .expect("Regex should be valid");
-      let desc = desc_end.replace(desc, "");
-
-      let desc = desc.trim().replace('\n', " ");
-      let desc = desc.trim_end_matches('.');
-      // Remove bogus escapes
-      let desc = desc.replace(r"\'", "").replace(r"\.", "");
-
-      let desc = trim_desc(&desc);
-
-      let desc = if desc.is_empty() { None } else { Some(desc) };
-      Some(Flag {
-        forms,
-        desc,
-        typ: None,
-      })
-    }
-    None => Some(Flag {
-      forms,
-      desc: None,
-      typ: None,
-    }),
+  #[test]
+  fn test_placeholder_type() {
+    assert_eq!(
+      Some(ArgType::Glob {
+        globs: vec!["*.tar.gz".to_owned()],
+        dirs: true,
+      }),
+      placeholder_type("FILE.tar.gz")
+    );
+    assert_eq!(None, placeholder_type("MAJOR.MINOR"));
+    assert_eq!(None, placeholder_type("FILE"));
+    assert_eq!(
+      Some(ArgType::Int {
+        min: None,
+        max: None
+      }),
+      placeholder_type("N")
+    );
+    assert_eq!(Some(ArgType::Duration), placeholder_type("timeout"));
+    assert_eq!(Some(ArgType::Url), placeholder_type("URL"));
+    assert_eq!(Some(ArgType::Text), placeholder_type("PATTERN"));
   }
 }
@@ -0,0 +1,26 @@
+//! Helpers for making commands and flags in tests
+
+use crate::{CommandInfo, Flag};
+
+/// A flag with the given forms and description
+pub fn flag(forms: &[&str], desc: Option<&str>) -> Flag {
+  Flag {
+    forms: forms.iter().map(|form| form.to_string()).collect(),
+    desc: desc.map(String::from),
+    ..Default::default()
+  }
+}
+
+/// A command with the given flags and subcommands
+pub fn cmd(
+  name: &str,
+  flags: Vec<Flag>,
+  subcommands: Vec<CommandInfo>,
+) -> CommandInfo {
+  CommandInfo {
+    name: name.to_owned(),
+    flags,
+    subcommands,
+    ..Default::default()
+  }
+}
@@ -0,0 +1,71 @@
+//! Test comparing commands with the `diff` subcommand
+
+use std::{env, path::PathBuf, process::Command};
+
+use assert_cmd::prelude::{CommandCargoExt, OutputAssertExt};
+
+const BIN_NAME: &str = "gen-completions";
+
+fn resources() -> PathBuf {
+  PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("tests/resources")
+}
+
+#[test]
+fn removals_fail() {
+  let diff_dir = resources().join("diff");
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .arg("diff")
+    .arg(diff_dir.join("old.kdl"))
+    .arg(diff_dir.join("new.kdl"))
+    .assert()
+    .failure()
+    .stdout(
+      r#"+ foo --color
++ foo new (subcommand)
+- foo old (subcommand)
+~ foo -v, --verbose: description "Verbose output" -> "Print more output"
+~ foo -q: forms -> -q, --quiet
+~ foo -o: type Path -> Dir
+"#,
+    );
+}
+
+#[test]
+fn additions_succeed() {
+  let diff_dir = resources().join("diff");
+  let output = Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .args(["diff", "--format", "json"])
+    .arg(diff_dir.join("new.kdl"))
+    .arg(diff_dir.join("old.kdl"))
+    .output()
+    .unwrap();
+  // Going the other way, the new subcommand, --color, and --quiet were removed
+  assert!(!output.status.success());
+  let report: serde_json::Value =
+    serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(3, report["removed"].as_array().unwrap().len());
+
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .arg("diff")
+    .arg(diff_dir.join("old.kdl"))
+    .arg(diff_dir.join("old.kdl"))
+    .assert()
+    .success()
+    .stdout("No differences\n");
+}
+
+#[test]
+fn man_page() {
+  let man_dir = resources().join("man");
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .env("MANPATH", man_dir.join("in"))
+    .args(["diff", "man:test1"])
+    .arg(man_dir.join("expected/test1.json"))
+    .assert()
+    .success()
+    .stdout("No differences\n");
+}
@@ -1,12 +1,16 @@
-//! Test generating completions from KDL/JSON files
+//! Test generating completions from KDL/JSON/TOML/YAML files
 
 use std::{
-  env,
+  env, fs,
   path::{Path, PathBuf},
   process::{Command, Stdio},
 };
 
 use assert_cmd::prelude::{CommandCargoExt, OutputAssertExt};
+use gen_completions::{
+  gen::{self, OutputFormat},
+  parse_deser::{self, InputFormat},
+};
 use insta::Settings;
 
 const BIN_NAME: &str = "gen-completions";
@@ -66,17 +70,365 @@ fn test1_json() {
   run_test("json", "test1.json", &[]);
 }
 
+#[test]
+fn test1_toml() {
+  run_test("kdl", "test1.toml", &[]);
+}
+
+#[test]
+fn test1_yaml() {
+  run_test("kdl", "test1.yaml", &[]);
+}
+
+#[test]
+fn test_args_zsh() {
+  run_test("zsh", "test-args.kdl", &[]);
+}
+
+#[test]
+fn test_args_bash() {
+  run_test("bash", "test-args.kdl", &[]);
+}
+
+#[test]
+fn test_args_nu() {
+  run_test("nu", "test-args.kdl", &[]);
+}
+
+#[test]
+fn test_args_kdl() {
+  run_test("kdl", "test-args.kdl", &[]);
+}
+
+#[test]
+fn test_args_carapace() {
+  run_test("carapace", "test-args.kdl", &[]);
+}
+
+#[test]
+fn test_relations_zsh() {
+  run_test("zsh", "test-relations.kdl", &[]);
+}
+
+#[test]
+fn test_relations_kdl() {
+  run_test("kdl", "test-relations.kdl", &[]);
+}
+
+#[test]
+fn test_relations_carapace() {
+  run_test("carapace", "test-relations.kdl", &[]);
+}
+
+#[test]
+fn test_persistent_zsh() {
+  run_test("zsh", "test-persistent.kdl", &[]);
+}
+
+#[test]
+fn test_persistent_bash() {
+  run_test("bash", "test-persistent.kdl", &[]);
+}
+
+#[test]
+fn test_persistent_nu() {
+  run_test("nu", "test-persistent.kdl", &[]);
+}
+
+#[test]
+fn test_persistent_kdl() {
+  run_test("kdl", "test-persistent.kdl", &[]);
+}
+
+#[test]
+fn test_persistent_carapace() {
+  run_test("carapace", "test-persistent.kdl", &[]);
+}
+
+#[test]
+fn test_hidden_zsh() {
+  run_test("zsh", "test-hidden.kdl", &[]);
+}
+
+#[test]
+fn test_hidden_bash() {
+  run_test("bash", "test-hidden.kdl", &[]);
+}
+
+#[test]
+fn test_hidden_nu() {
+  run_test("nu", "test-hidden.kdl", &[]);
+}
+
+#[test]
+fn test_hidden_kdl() {
+  run_test("kdl", "test-hidden.kdl", &[]);
+}
+
+#[test]
+fn test_hidden_carapace() {
+  run_test("carapace", "test-hidden.kdl", &[]);
+}
+
+#[test]
+fn test_positional_zsh() {
+  run_test("zsh", "test-positional.kdl", &[]);
+}
+
+#[test]
+fn test_positional_bash() {
+  run_test("bash", "test-positional.kdl", &[]);
+}
+
+#[test]
+fn test_positional_nu() {
+  run_test("nu", "test-positional.kdl", &[]);
+}
+
+#[test]
+fn test_positional_kdl() {
+  run_test("kdl", "test-positional.kdl", &[]);
+}
+
+#[test]
+fn test_positional_carapace() {
+  run_test("carapace", "test-positional.kdl", &[]);
+}
+
+#[test]
+fn test_builtins_zsh() {
+  run_test("zsh", "test-builtins.kdl", &[]);
+}
+
+#[test]
+fn test_builtins_bash() {
+  run_test("bash", "test-builtins.kdl", &[]);
+}
+
+#[test]
+fn test_builtins_nu() {
+  run_test("nu", "test-builtins.kdl", &[]);
+}
+
+#[test]
+fn test_builtins_kdl() {
+  run_test("kdl", "test-builtins.kdl", &[]);
+}
+
+#[test]
+fn test_builtins_carapace() {
+  run_test("carapace", "test-builtins.kdl", &[]);
+}
+
+#[test]
+fn test_globs_zsh() {
+  run_test("zsh", "test-globs.kdl", &[]);
+}
+
+#[test]
+fn test_globs_bash() {
+  run_test("bash", "test-globs.kdl", &[]);
+}
+
+#[test]
+fn test_globs_nu() {
+  run_test("nu", "test-globs.kdl", &[]);
+}
+
+#[test]
+fn test_globs_kdl() {
+  run_test("kdl", "test-globs.kdl", &[]);
+}
+
+#[test]
+fn test_globs_carapace() {
+  run_test("carapace", "test-globs.kdl", &[]);
+}
+
+#[test]
+fn test_values_zsh() {
+  run_test("zsh", "test-values.kdl", &[]);
+}
+
+#[test]
+fn test_values_bash() {
+  run_test("bash", "test-values.kdl", &[]);
+}
+
+#[test]
+fn test_values_nu() {
+  run_test("nu", "test-values.kdl", &[]);
+}
+
+#[test]
+fn test_values_kdl() {
+  run_test("kdl", "test-values.kdl", &[]);
+}
+
+#[test]
+fn test_values_carapace() {
+  run_test("carapace", "test-values.kdl", &[]);
+}
+
+#[test]
+fn test_lists_zsh() {
+  run_test("zsh", "test-lists.kdl", &[]);
+}
+
+#[test]
+fn test_lists_bash() {
+  run_test("bash", "test-lists.kdl", &[]);
+}
+
+#[test]
+fn test_lists_nu() {
+  run_test("nu", "test-lists.kdl", &[]);
+}
+
+#[test]
+fn test_lists_kdl() {
+  run_test("kdl", "test-lists.kdl", &[]);
+}
+
+#[test]
+fn test_lists_carapace() {
+  run_test("carapace", "test-lists.kdl", &[]);
+}
+
+#[test]
+fn test_run_zsh() {
+  run_test("zsh", "test-run.yaml", &[]);
+}
+
+#[test]
+fn test_run_bash() {
+  run_test("bash", "test-run.yaml", &[]);
+}
+
+#[test]
+fn test_run_carapace() {
+  run_test("carapace", "test-run.yaml", &[]);
+}
+
+/// Generate KDL from some config file, given some extra arguments
+fn gen_kdl(args: &[&str], stdin: Option<&Path>) -> String {
+  let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+  cmd.arg("for").arg("kdl").args(args);
+  if let Some(stdin) = stdin {
+    cmd.stdin(fs::File::open(stdin).unwrap());
+  }
+  let output = cmd.output().unwrap();
+  assert!(output.status.success(), "{output:?}");
+  String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn stdin_and_input_format() {
+  let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+    .join("tests/resources/gen");
+  let json = dir.join("test1.json");
+  let toml = dir.join("test1.toml");
+  let expected = gen_kdl(&[json.to_str().unwrap()], None);
+
+  // Format is sniffed
+  assert_eq!(expected, gen_kdl(&["-"], Some(&toml)));
+  // Format is given explicitly
+  assert_eq!(
+    expected,
+    gen_kdl(&["-", "--input-format", "toml"], Some(&toml))
+  );
+
+  // No extension, so format is sniffed
+  let tmp = tempfile::tempdir().unwrap();
+  let spec = tmp.path().join("test1");
+  fs::copy(&toml, &spec).unwrap();
+  assert_eq!(expected, gen_kdl(&[spec.to_str().unwrap()], None));
+
+  // Extension doesn't match the format, but the explicit format wins
+  let spec = tmp.path().join("test1.json");
+  fs::copy(&toml, &spec).unwrap();
+  assert_eq!(
+    expected,
+    gen_kdl(&[spec.to_str().unwrap(), "--input-format=toml"], None)
+  );
+}
+
+#[test]
+fn directory_input() {
+  let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+    .join("tests/resources/gen/multi");
+  let out = tempfile::tempdir().unwrap();
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .arg("for")
+    .arg("kdl,json")
+    .arg(&dir)
+    .arg(out.path())
+    .assert()
+    .success();
+
+  for format in ["kdl", "json"] {
+    let mut files = fs::read_dir(out.path().join(format))
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+      .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(
+      vec!["bar", "baz", "foo", "qux"]
+        .into_iter()
+        .map(|name| format!("{name}.{format}"))
+        .collect::<Vec<_>>(),
+      files
+    );
+  }
+
+  // Multiple commands can't all go to stdout
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .arg("for")
+    .arg("kdl")
+    .arg(dir.join("tools.kdl"))
+    .assert()
+    .failure();
+}
+
 // #[test]
 // fn types_bash() {
 //   run_test("bash", "test-types.kdl", &[]);
 // }
 
-// #[test]
-// fn types_zsh() {
-//   run_test("zsh", "test-types.kdl", &[]);
-// }
+#[test]
+fn types_zsh() {
+  run_test("zsh", "test-types.kdl", &[]);
+}
 
 #[test]
 fn types_nu() {
   run_test("nu", "test-types.kdl", &[]);
 }
+
+/// Converting any of the config files to KDL and reading it back shouldn't
+/// lose anything
+#[test]
+fn kdl_round_trip() {
+  let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+  let mut files = fs::read_dir(PathBuf::from(root).join("tests/resources/gen"))
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.is_file())
+    .collect::<Vec<_>>();
+  files.sort();
+
+  for file in files {
+    let cmd = parse_deser::parse(&file).unwrap();
+    let kdl = gen::generate_to_str(&cmd, OutputFormat::Kdl, 0);
+    let read_back = parse_deser::parse_from_str(&kdl, InputFormat::Kdl)
+      .unwrap_or_else(|e| panic!("{}: {e:?}\n{kdl}", file.display()));
+    assert_eq!(
+      gen::generate_to_str(&cmd, OutputFormat::Json, 0),
+      gen::generate_to_str(&read_back, OutputFormat::Json, 0),
+      "{} changed going through KDL:\n{kdl}",
+      file.display()
+    );
+  }
+}
@@ -0,0 +1,43 @@
+//! Test KDL files that include other files
+
+use std::{env, path::PathBuf, process::Command};
+
+use assert_cmd::prelude::CommandCargoExt;
+
+const BIN_NAME: &str = "gen-completions";
+
+fn resources() -> PathBuf {
+  PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+    .join("tests/resources/include")
+}
+
+#[test]
+fn includes_resolved() {
+  let cmd =
+    gen_completions::parse_deser::parse(resources().join("main.kdl")).unwrap();
+  let get = &cmd.subcommands[0];
+  let forms = get
+    .flags
+    .iter()
+    .map(|flag| flag.forms.join(","))
+    .collect::<Vec<_>>();
+  assert_eq!(vec!["--verbose", "--config", "-n,--namespace"], forms);
+  assert_eq!(2, cmd.flags.len());
+}
+
+#[test]
+fn errors_in_included_files() {
+  let output = Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .env("NO_COLOR", "1")
+    .arg("for")
+    .arg("kdl")
+    .arg(resources().join("bad.kdl"))
+    .output()
+    .unwrap();
+  assert!(!output.status.success());
+  let stderr = String::from_utf8(output.stderr).unwrap();
+  // The error should be shown in the included file, not the including one
+  assert!(stderr.contains("bad-types.kdl"), "{stderr}");
+  assert!(stderr.contains("not-a-type"), "{stderr}");
+}
@@ -0,0 +1,64 @@
+//! Test checking config files with the `lint` subcommand
+
+use std::{env, path::PathBuf, process::Command};
+
+use assert_cmd::prelude::{CommandCargoExt, OutputAssertExt};
+
+const BIN_NAME: &str = "gen-completions";
+
+fn resources() -> PathBuf {
+  PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("tests/resources")
+}
+
+#[test]
+fn bad_kdl() {
+  let output = Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .env("NO_COLOR", "1")
+    .arg("lint")
+    .arg(resources().join("lint/bad.kdl"))
+    .output()
+    .unwrap();
+  assert!(!output.status.success());
+
+  let stderr = String::from_utf8(output.stderr).unwrap();
+  for code in [
+    "lint::flag_without_dash",
+    "lint::empty_strings",
+    "lint::subcommand_whitespace",
+    "lint::duplicate_subcommand",
+  ] {
+    assert!(stderr.contains(code), "{code} not reported in:\n{stderr}");
+  }
+  // The empty strings type is only a warning
+  assert!(stderr.contains("Found 3 problems"));
+}
+
+#[test]
+fn warnings() {
+  let file = resources().join("lint/warnings.kdl");
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .arg("lint")
+    .arg(&file)
+    .assert()
+    .success();
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .args(["lint", "--deny-warnings"])
+    .arg(&file)
+    .assert()
+    .failure();
+}
+
+#[test]
+fn good_files() {
+  for file in ["gen/test1.json", "gen/test-types.kdl"] {
+    Command::cargo_bin(BIN_NAME)
+      .unwrap()
+      .arg("lint")
+      .arg(resources().join(file))
+      .assert()
+      .success();
+  }
+}
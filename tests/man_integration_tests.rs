@@ -25,9 +25,10 @@ fn run_test(shell: &str, outputs: &[&str], args: &[&str]) {
   let cmd = cmd
     .env("MANPATH", &in_dir)
     .arg("man")
+    .arg("--no-cache")
     .args(args)
     .arg(shell)
-    .arg(&out_dir.path().display().to_string());
+    .arg(out_dir.path().display().to_string());
 
   // So we can explicitly ask for logging
   if let Ok(log_level) = env::var("RUST_LOG") {
@@ -94,7 +95,7 @@ fn run_test(shell: &str, outputs: &[&str], args: &[&str]) {
 
       // Copy the incorrect output out of the temp directory
       let saved = failed_dir.join(file_name);
-      let got = fs::read(&out_dir.path().join(file_name)).unwrap();
+      let got = fs::read(out_dir.path().join(file_name)).unwrap();
       fs::write(&saved, got).unwrap();
 
       let saved = saved.display().to_string();
@@ -143,3 +144,112 @@ fn podman_ncdu_json() {
 fn darwin_bless_json() {
   run_test("json", &["bless"], &["--cmds", "^bless"]);
 }
+
+#[test]
+fn commands_section_test2_json() {
+  run_test("json", &["test2"], &["--cmds", "^test2"]);
+}
+
+#[test]
+fn multiple_shells() {
+  let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+  let test_resources = PathBuf::from(root).join("tests/resources/man");
+  let out_dir = tempfile::tempdir().unwrap();
+  let nu_dir = out_dir.path().join("custom-nu");
+
+  let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+  cmd
+    .env("MANPATH", test_resources.join("in"))
+    .args(["man", "--no-cache", "--cmds", "^test1", "--out-nu"])
+    .arg(&nu_dir)
+    .arg("zsh,json,nu")
+    .arg(out_dir.path())
+    .assert()
+    .success();
+
+  // The man page should only be parsed once, but generated for every shell
+  assert_eq!(
+    fs::read(test_resources.join("expected/test1.json")).unwrap(),
+    fs::read(out_dir.path().join("json/test1.json")).unwrap()
+  );
+  assert!(out_dir.path().join("zsh/_test1.zsh").exists());
+  assert!(nu_dir.join("test1-completions.nu").exists());
+  assert!(!out_dir.path().join("nu").exists());
+
+  out_dir.close().unwrap();
+}
+
+#[test]
+fn cache_reused() {
+  let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+  let test_resources = PathBuf::from(root).join("tests/resources/man");
+  let out_dir = tempfile::tempdir().unwrap();
+  let cache_dir = tempfile::tempdir().unwrap();
+
+  let run = || {
+    Command::cargo_bin(BIN_NAME)
+      .unwrap()
+      .env("MANPATH", test_resources.join("in"))
+      .env("XDG_CACHE_HOME", cache_dir.path())
+      .args(["man", "--cmds", "^test1", "json"])
+      .arg(out_dir.path())
+      .assert()
+      .success();
+  };
+
+  run();
+  let entry = cache_dir.path().join("gen-completions/test1.json");
+  assert!(entry.exists());
+  // Detected subcommands are cached so their man pages needn't be read again
+  assert!(fs::read_to_string(&entry)
+    .unwrap()
+    .contains(r#""cmd":["test1","sub1","nested"]"#));
+  let out_file = out_dir.path().join("test1.json");
+  let modified = fs::metadata(&out_file).unwrap().modified().unwrap();
+
+  // Tamper with the cache entry to make sure it's actually being used
+  let cached = fs::read_to_string(&entry)
+    .unwrap()
+    .replace("Verbose output", "Cached description");
+  fs::write(&entry, cached).unwrap();
+  run();
+  let got = fs::read_to_string(&out_file).unwrap();
+  assert!(got.contains("Cached description"));
+  assert!(fs::metadata(&out_file).unwrap().modified().unwrap() >= modified);
+
+  // Running again with the same cache shouldn't rewrite the output
+  let modified = fs::metadata(&out_file).unwrap().modified().unwrap();
+  run();
+  assert_eq!(
+    modified,
+    fs::metadata(&out_file).unwrap().modified().unwrap()
+  );
+
+  out_dir.close().unwrap();
+  cache_dir.close().unwrap();
+}
+
+#[test]
+fn overlay() {
+  let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+  let test_resources = PathBuf::from(root).join("tests/resources/man");
+  let out_dir = tempfile::tempdir().unwrap();
+
+  Command::cargo_bin(BIN_NAME)
+    .unwrap()
+    .env("MANPATH", test_resources.join("in"))
+    .args(["man", "--no-cache", "--cmds", "^test1", "--overlay"])
+    .arg(test_resources.join("overlay"))
+    .arg("json")
+    .arg(out_dir.path())
+    .assert()
+    .success();
+
+  let got = fs::read_to_string(out_dir.path().join("test1.json")).unwrap();
+  assert_eq!(
+    r#"{"$schema":"https://raw.githubusercontent.com/ysthakur/gen-completions/main/schemas/command.json","name":"test1","flags":[{"forms":["-v","--verbose","--loud"],"desc":"Print more output","arg":{"count":"One","name":"foo,bar"}}],"subcommands":[{"name":"renamed","flags":[{"forms":["--foobar"],"desc":"Something something [brackets]"}],"subcommands":[{"name":"nested","flags":[{"forms":["-co","--command"],"desc":"Run a command or something"},{"forms":["--install"],"desc":"Install a thing"}]}]},{"name":"sub2","flags":[{"forms":["--a","--all-foo","--all-bar"],"desc":"Both options should be picked up even though the short one is weird"},{"forms":["-C"],"desc":"Backup directory","typ":"Dir"}]}]}"#,
+    got.trim()
+  );
+
+  out_dir.close().unwrap();
+}